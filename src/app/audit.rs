@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+
+use keepass::{Database, db::GroupRef};
+
+use super::{config::SourceConfig, inspect::KeepassCall};
+
+/// Call of the keepass helper in a template, or in one of its partials
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryUse {
+    pub template: String,
+    pub output: String,
+    pub source: Option<SourceConfig>,
+    /// File with the call, the template or the partial
+    pub file: String,
+    /// The path includes the group of the profile
+    pub call: KeepassCall,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct AuditReport {
+    /// Uses of each entry, with the path of the database when it exists
+    pub entries: BTreeMap<String, Vec<EntryUse>>,
+    /// Paths used by the templates that are not in the database
+    pub missing: Vec<String>,
+    pub unused: Vec<String>,
+}
+
+fn collect_entries(group: GroupRef, path: &[String], db: &Database, paths: &mut Vec<String>) {
+    for entry in group.entries() {
+        if let Some(title) = entry.get_title() {
+            let mut entry_path = path.to_vec();
+            entry_path.push(title.to_string());
+            paths.push(entry_path.join("/"));
+        }
+    }
+    for child in group.groups() {
+        if db.meta.recyclebin_uuid == Some(child.id().uuid()) {
+            continue;
+        }
+        let mut child_path = path.to_vec();
+        child_path.push(child.name.clone());
+        collect_entries(child, &child_path, db, paths);
+    }
+}
+
+/// Paths of all the entries of the database, skipping the recycle bin
+pub fn entry_paths(db: &Database) -> Vec<String> {
+    let mut paths = Vec::new();
+    collect_entries(db.root(), &[], db, &mut paths);
+    paths.sort();
+    paths
+}
+
+/// The entries are found ignoring the case, like the keepass helper does. The
+/// filter selects an entry or all the entries of a group
+pub fn audit_entries(entries: &[String], uses: Vec<EntryUse>, filter: Option<&str>) -> AuditReport {
+    let filter = filter.map(|filter| filter.trim_matches('/').to_lowercase());
+    let selected = |path: &str| {
+        let path = path.to_lowercase();
+        filter
+            .as_ref()
+            .is_none_or(|filter| path == *filter || path.starts_with(&format!("{filter}/")))
+    };
+
+    let mut report = AuditReport::default();
+    for entry_use in uses {
+        let path = entry_use.call.path.join("/");
+        if !selected(&path) {
+            continue;
+        }
+        let entry = entries
+            .iter()
+            .find(|entry| entry.eq_ignore_ascii_case(&path));
+        if entry.is_none() && !report.missing.contains(&path) {
+            report.missing.push(path.clone());
+        }
+        let key = entry.cloned().unwrap_or(path);
+        report.entries.entry(key).or_default().push(entry_use);
+    }
+    report.unused = entries
+        .iter()
+        .filter(|entry| selected(entry) && !report.entries.contains_key(*entry))
+        .cloned()
+        .collect();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::test_helpers::tests::get_test_db;
+
+    fn entry_use(path: &str, template: &str) -> EntryUse {
+        EntryUse {
+            template: template.to_string(),
+            output: format!("{template}.env"),
+            source: Some(SourceConfig::Project),
+            file: template.to_string(),
+            call: KeepassCall {
+                helper: String::from("keepass"),
+                path: path.split('/').map(String::from).collect(),
+                field: String::from("password"),
+                line: 1,
+                column: 1,
+            },
+        }
+    }
+
+    #[test]
+    fn test_entry_paths() {
+        let paths = entry_paths(&get_test_db());
+
+        assert!(paths.contains(&String::from("test1")));
+        assert!(paths.contains(&String::from("group1/test2")));
+        assert!(paths.contains(&String::from("group1/Some weird name")));
+    }
+
+    #[test]
+    fn test_audit_entries() {
+        let entries = vec![
+            String::from("complex"),
+            String::from("group1/test2"),
+            String::from("group1/test3"),
+            String::from("test1"),
+        ];
+        let uses = vec![
+            entry_use("group1/TEST2", "api"),
+            entry_use("group1/test2", "worker"),
+            entry_use("test1", "api"),
+            entry_use("group1/unknown", "api"),
+        ];
+
+        let report = audit_entries(&entries, uses.clone(), None);
+        let templates: Vec<(&str, Vec<&str>)> = report
+            .entries
+            .iter()
+            .map(|(entry, uses)| {
+                let templates = uses.iter().map(|u| u.template.as_str()).collect();
+                (entry.as_str(), templates)
+            })
+            .collect();
+        assert_eq!(
+            templates,
+            vec![
+                ("group1/test2", vec!["api", "worker"]),
+                ("group1/unknown", vec!["api"]),
+                ("test1", vec!["api"])
+            ]
+        );
+        assert_eq!(report.missing, vec!["group1/unknown"]);
+        assert_eq!(report.unused, vec!["complex", "group1/test3"]);
+
+        let report = audit_entries(&entries, uses, Some("Group1"));
+        assert_eq!(
+            report.entries.keys().collect::<Vec<&String>>(),
+            vec!["group1/test2", "group1/unknown"]
+        );
+        assert_eq!(report.unused, vec!["group1/test3"]);
+    }
+}
@@ -0,0 +1,53 @@
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+const OVERWRITE_CHUNK: usize = 64 * 1024;
+
+/// Overwrites the contents of the file with zeros before it is removed. On
+/// copy-on-write filesystems and SSDs this doesn't guarantee that the old
+/// blocks are gone, but it avoids leaving the secrets in a recoverable file.
+fn overwrite_file(path: &Path) -> std::io::Result<()> {
+    let length = std::fs::metadata(path)?.len() as usize;
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let zeros = vec![0u8; OVERWRITE_CHUNK.min(length)];
+    let mut remaining = length;
+    while remaining > 0 {
+        let chunk = remaining.min(OVERWRITE_CHUNK);
+        file.write_all(&zeros[..chunk])?;
+        remaining -= chunk;
+    }
+    file.sync_all()
+}
+
+pub fn remove_output(path: &Path, secure: bool) -> std::io::Result<()> {
+    if secure {
+        overwrite_file(path)?;
+    }
+    std::fs::remove_file(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_helpers::TmpFile;
+
+    use super::*;
+
+    #[test]
+    fn test_overwrite_file() {
+        let file = TmpFile::new_uuid("test_resources/tmp", Some("env"));
+        file.write(String::from("SECRET=value"));
+
+        overwrite_file(Path::new(&file.get())).unwrap();
+
+        assert_eq!(std::fs::read(file.get()).unwrap(), vec![0u8; 12]);
+    }
+
+    #[test]
+    fn test_remove_output_secure() {
+        let file = TmpFile::new_uuid("test_resources/tmp", Some("env"));
+        file.write(String::from("SECRET=value"));
+
+        remove_output(Path::new(&file.get()), true).unwrap();
+
+        assert!(!Path::new(&file.get()).exists());
+    }
+}
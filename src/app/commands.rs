@@ -1,6 +1,9 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use clap_complete::Shell;
 
+use super::export::{ExportFormat, KeyNaming};
+use super::generators::OutputGenerator;
+
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -40,20 +43,291 @@ pub enum Commands {
         )]
         relative_to_input: bool,
 
-        output: String,
+        #[arg(help = "Output of the template, by default the one declared in the front-matter")]
+        output: Option<String>,
 
         #[arg(short, long, help = "Overwrite the global keepass file")]
         keepass: Option<String>,
 
-        #[arg(short, long, help = "Add or overwrite variables into the build")]
+        #[arg(
+            short,
+            long,
+            help = "Add or overwrite variables into the build, use var:=<json> for typed values"
+        )]
         vars: Vec<String>,
+
+        #[arg(long, help = "Load the variables of a yaml or json file")]
+        vars_file: Option<String>,
+
+        #[command(flatten)]
+        generator: GeneratorArgs,
+
+        #[arg(
+            short,
+            long,
+            help = "Keep running and build again when the template or the KeePass file change"
+        )]
+        watch: bool,
+
+        #[arg(long, help = "Use the settings of a profile of the configuration")]
+        profile: Option<String>,
+
+        #[arg(
+            long,
+            help = "Save the answers of the missing required variables in the project configuration"
+        )]
+        save_variables: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "watch",
+            help = "Allow adding the entries with generate=true to the KeePass file, a backup is created before saving it"
+        )]
+        allow_db_write: bool,
     },
 
     /// Build all the templates in the configuration
     BuildAll {
-        #[arg(short, long, help = "Add or overwrite variables into the build")]
+        #[arg(
+            short,
+            long,
+            help = "Add or overwrite variables into the build, use var:=<json> for typed values"
+        )]
+        vars: Vec<String>,
+
+        #[arg(long, help = "Load the variables of a yaml or json file")]
+        vars_file: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            help = "Keep running and build again when the templates or the KeePass file change"
+        )]
+        watch: bool,
+
+        #[arg(long, help = "Use the settings of a profile of the configuration")]
+        profile: Option<String>,
+
+        #[arg(
+            long,
+            help = "Save the answers of the missing required variables in the project configuration"
+        )]
+        save_variables: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "watch",
+            help = "Allow adding the entries with generate=true to the KeePass file, a backup is created before saving it"
+        )]
+        allow_db_write: bool,
+
+        #[arg(
+            long,
+            help = "Build the outputs that are up to date according to the lock file too"
+        )]
+        force: bool,
+
+        #[arg(
+            long,
+            help = "Run the hooks of the project configuration without asking for confirmation"
+        )]
+        allow_hooks: bool,
+    },
+
+    /// Remove all the outputs generated by the templates in the configuration
+    Clean {
+        #[arg(long, help = "Only show the files that would be removed")]
+        dry_run: bool,
+
+        #[arg(
+            long,
+            help = "Overwrite the contents of the files before removing them"
+        )]
+        secure: bool,
+
+        #[arg(
+            short,
+            long,
+            conflicts_with = "global",
+            help = "Only remove the outputs of the current project configuration"
+        )]
+        local: bool,
+
+        #[arg(
+            short,
+            long,
+            help = "Only remove the outputs of the global configuration"
+        )]
+        global: bool,
+    },
+
+    /// Decrypt a file encrypted with age to check its contents
+    Decrypt {
+        file: String,
+
+        #[arg(short, long, help = "File with the age identities to decrypt the file")]
+        identity: String,
+
+        #[arg(short, long, help = "File to write, when missing it is printed")]
+        output: Option<String>,
+    },
+
+    /// Export all the entries of a KeePass group without a template
+    Export {
+        #[arg(help = "Path of the group, like Projects/api, use / for the root group")]
+        group: String,
+
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Dotenv)]
+        format: ExportFormat,
+
+        #[arg(short, long, help = "File to write, when missing it is printed")]
+        output: Option<String>,
+
+        #[arg(short, long, help = "Overwrite the global keepass file")]
+        keepass: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = KeyNaming::UpperSnake, help = "How to convert the entry title into the key")]
+        naming: KeyNaming,
+
+        #[arg(
+            long,
+            help = "Use the value of this entry attribute as the key when the entry has it"
+        )]
+        key_attribute: Option<String>,
+
+        #[arg(long, default_value = "", help = "Prefix added to every key")]
+        prefix: String,
+
+        #[arg(
+            long,
+            value_delimiter = ',',
+            default_value = "password",
+            help = "Fields to export, when more than one is selected the field name is appended to the key"
+        )]
+        fields: Vec<String>,
+
+        #[command(flatten)]
+        generator: GeneratorArgs,
+    },
+
+    /// Move the secrets of a dotenv file into the KeePass file and write a template for it
+    Import {
+        #[arg(help = "Dotenv file to import")]
+        file: String,
+
+        #[arg(
+            short,
+            long,
+            help = "Group where the entries are created, like Projects/legacy"
+        )]
+        group: String,
+
+        #[arg(short, long, help = "Overwrite the global keepass file")]
+        keepass: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            help = "Template to write, by default the file with the .hbs extension"
+        )]
+        template: Option<String>,
+
+        #[arg(long, help = "Import this key even if it doesn't look like a secret")]
+        secret: Vec<String>,
+
+        #[arg(long, help = "Add the template to the project configuration")]
+        add_file: bool,
+    },
+
+    /// List the entries, variables and partials used by the templates, without opening the KeePass file
+    Inspect {
+        #[arg(help = "Template to inspect, by default all the templates of the configuration")]
+        template: Option<String>,
+    },
+
+    /// Write back to the KeePass file the values edited in the output of a template
+    SyncBack {
+        template: String,
+
+        #[arg(help = "Output of the template, by default the one declared in the front-matter")]
+        output: Option<String>,
+
+        #[arg(short, long, help = "Overwrite the global keepass file")]
+        keepass: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            help = "Add or overwrite variables into the build, use var:=<json> for typed values"
+        )]
+        vars: Vec<String>,
+    },
+
+    /// List the templates and outputs that use each KeePass entry, and the entries not used
+    Audit {
+        #[arg(long, help = "Only show this entry, or the entries of this group")]
+        entry: Option<String>,
+
+        #[arg(long, help = "Use the settings of a profile of the configuration")]
+        profile: Option<String>,
+    },
+
+    /// Check all the templates against the KeePass file without writing the outputs
+    Validate {
+        #[arg(
+            short,
+            long,
+            help = "Add or overwrite variables into the build, use var:=<json> for typed values"
+        )]
         vars: Vec<String>,
+
+        #[arg(long, help = "Load the variables of a yaml or json file")]
+        vars_file: Option<String>,
+
+        #[arg(long, help = "Use the settings of a profile of the configuration")]
+        profile: Option<String>,
     },
+
+    /// Show the outputs that changed their inputs since they were built
+    Status {
+        #[arg(
+            short,
+            long,
+            help = "Add or overwrite variables into the build, use var:=<json> for typed values"
+        )]
+        vars: Vec<String>,
+
+        #[arg(long, help = "Load the variables of a yaml or json file")]
+        vars_file: Option<String>,
+
+        #[arg(long, help = "Use the settings of a profile of the configuration")]
+        profile: Option<String>,
+    },
+}
+
+#[derive(Debug, Args, Clone, Default)]
+pub struct GeneratorArgs {
+    #[arg(
+        long,
+        value_enum,
+        help = "Convert the values into a kubernetes secret, a docker secrets directory or a systemd layout"
+    )]
+    pub generator: Option<OutputGenerator>,
+
+    #[arg(
+        long,
+        help = "Name of the kubernetes secret, by default the name of the output file"
+    )]
+    pub secret_name: Option<String>,
+
+    #[arg(long, help = "Namespace of the kubernetes secret")]
+    pub namespace: Option<String>,
+
+    #[arg(
+        long = "label",
+        help = "Labels of the kubernetes secret defined as key=value"
+    )]
+    pub labels: Vec<String>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -79,7 +353,8 @@ pub enum ConfigCommands {
         name: Option<String>,
 
         template: String,
-        output: String,
+        #[arg(help = "Output of the template, by default the one declared in the front-matter")]
+        output: Option<String>,
         #[arg(
             short,
             long,
@@ -92,6 +367,21 @@ pub enum ConfigCommands {
             help = "add the file in the current project configuration"
         )]
         local: bool,
+        #[arg(long = "var", help = "Variables of the template, defined as var=value")]
+        vars: Vec<String>,
+    },
+
+    /// Add or overwrite variables of the templates with the name
+    SetTemplateVar {
+        name: String,
+        #[arg(num_args=1.., help="Variables defined as var=value")]
+        variables: Vec<String>,
+        #[arg(
+            short,
+            long,
+            help = "change the templates of the current project configuration"
+        )]
+        local: bool,
     },
 
     /// Deletes all templates that the source doesn't exists
@@ -116,12 +406,24 @@ pub enum ConfigCommands {
     AddVariables {
         #[arg(num_args=1.., help="Variables defined as var=value, when existing will be overwrited")]
         variables: Vec<String>,
+        #[arg(
+            short,
+            long,
+            help = "add the variables in the current project configuration"
+        )]
+        local: bool,
     },
 
     /// Delete a variable variable
     DeleteVariables {
         #[arg(num_args=1..)]
         variables: Vec<String>,
+        #[arg(
+            short,
+            long,
+            help = "delete the variables in the current project configuration"
+        )]
+        local: bool,
     },
 }
 
@@ -141,10 +443,75 @@ mod tests {
         ]);
 
         assert!(
-            matches!(cli.command, Commands::BuildAll { vars } if vars == Vec::from(["email=something".to_string(), "email2=j@k.com".to_string()]))
+            matches!(cli.command, Commands::BuildAll { vars, watch: false, profile: None, vars_file: None, save_variables: false, allow_db_write: false, force: false, allow_hooks: false } if vars == ["email=something".to_string(), "email2=j@k.com".to_string()])
         );
     }
 
+    #[test]
+    fn test_cli_export() {
+        let cli = Cli::parse_from([
+            "kp2f",
+            "export",
+            "Projects/api",
+            "-f",
+            "json",
+            "--fields",
+            "username,password",
+        ]);
+
+        match cli.command {
+            Commands::Export {
+                group,
+                format,
+                output,
+                naming,
+                prefix,
+                fields,
+                ..
+            } => {
+                assert_eq!(group, "Projects/api");
+                assert_eq!(format, ExportFormat::Json);
+                assert_eq!(output, None);
+                assert_eq!(naming, KeyNaming::UpperSnake);
+                assert_eq!(prefix, "");
+                assert_eq!(fields, vec!["username", "password"]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_cli_build_k8s_secret() {
+        let cli = Cli::parse_from([
+            "kp2f",
+            "build",
+            "file.env.example",
+            "secret.yaml",
+            "--generator",
+            "k8s-secret",
+            "--namespace",
+            "staging",
+            "--label",
+            "app=api",
+        ]);
+
+        match cli.command {
+            Commands::Build { generator, .. } => {
+                assert_eq!(generator.generator, Some(OutputGenerator::K8sSecret));
+                assert_eq!(generator.secret_name, None);
+                assert_eq!(generator.namespace, Some(String::from("staging")));
+                assert_eq!(generator.labels, vec!["app=api"]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_cli_clean_local_and_global_conflict() {
+        let result = Cli::try_parse_from(["kp2f", "clean", "--local", "--global"]);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_cli_build_variables() {
         let cli = Cli::parse_from([
@@ -166,17 +533,44 @@ mod tests {
                 output,
                 keepass,
                 vars,
+                generator,
+                watch,
+                profile,
+                vars_file,
+                save_variables,
+                allow_db_write,
             } => {
+                assert!(!save_variables);
+                assert!(!allow_db_write);
+                assert_eq!(generator.generator, None);
+                assert_eq!(vars_file, None);
+                assert!(!watch);
+                assert_eq!(profile, None);
                 assert_eq!(
                     vars,
                     Vec::from(["email=something".to_string(), "email2=j@k.com".to_string()])
                 );
-                assert_eq!(output, ".env");
-                assert_eq!(relative_to_input, true);
+                assert_eq!(output, Some(String::from(".env")));
+                assert!(relative_to_input);
                 assert_eq!(template, "file.env.example");
                 assert_eq!(keepass, None);
             }
-            _ => assert!(false),
+            _ => unreachable!(),
         }
     }
+
+    #[test]
+    fn test_cli_allow_db_write_conflicts_with_watch() {
+        let result = Cli::try_parse_from(["kp2f", "build-all", "--watch", "--allow-db-write"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cli_build_all_profile() {
+        let cli = Cli::parse_from(["kp2f", "build-all", "--profile", "staging"]);
+
+        assert!(
+            matches!(cli.command, Commands::BuildAll { profile: Some(profile), .. } if profile == "staging")
+        );
+    }
 }
@@ -1,8 +1,17 @@
 pub mod yaml;
 
-use std::{fmt::Display, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
-use yaml::YamlConfig;
+use serde_json::Value;
+
+use super::escaping::EscapeStrategy;
+use yaml::{
+    RequiredVariables, Variables, YamlConfig, YamlConfigEncrypt, YamlConfigHooks, YamlConfigProfile,
+};
 
 #[derive(Debug)]
 pub struct ConfigError {
@@ -29,6 +38,7 @@ pub struct ConfigHandler {
 }
 
 pub const PROJECT_FILE: &str = ".keepass-2-file";
+pub const LOCK_FILE: &str = ".keepass-2-file.lock";
 
 impl ConfigHandler {
     fn get_project_file(project: &String) -> Option<PathBuf> {
@@ -78,6 +88,30 @@ impl ConfigHandler {
         &self.file
     }
 
+    pub fn project_dir(&self) -> &str {
+        &self.project
+    }
+
+    /// The lock file is kept next to the project configuration, there is none
+    /// without it
+    pub fn lock_file(&self) -> Option<PathBuf> {
+        self.local.as_ref()?;
+        Some(Self::get_project_file(&self.project)?.with_file_name(LOCK_FILE))
+    }
+
+    /// The key of the hashes of the lock files is kept next to the global
+    /// configuration, out of the projects
+    pub fn lock_key_file(&self) -> PathBuf {
+        Path::new(&self.file).with_extension("key")
+    }
+
+    /// Paths inside the project are kept relative to it, like in its configuration
+    pub fn project_relative(&self, path: &str) -> String {
+        path.strip_prefix(&format!("{}/", self.project))
+            .unwrap_or(path)
+            .to_string()
+    }
+
     pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
         let global_content = self.global.yaml()?;
         std::fs::write(&self.file, global_content)?;
@@ -92,7 +126,7 @@ impl ConfigHandler {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SourceConfig {
     Project,
     Global,
@@ -114,6 +148,11 @@ pub struct TemplateInfo {
     pub name: Option<String>,
     pub template: String,
     pub output: String,
+    pub encrypt: Option<YamlConfigEncrypt>,
+    pub hooks: Option<YamlConfigHooks>,
+    pub variables: Variables,
+    pub required_variables: RequiredVariables,
+    pub escape: Option<EscapeStrategy>,
 }
 
 impl ConfigHandler {
@@ -129,16 +168,75 @@ impl ConfigHandler {
         }
     }
 
+    pub fn database(&self, alias: &str) -> Result<String, ConfigError> {
+        self.local
+            .as_ref()
+            .and_then(|local| local.get_database(alias))
+            .or_else(|| self.global.get_database(alias))
+            .ok_or_else(|| {
+                ConfigError::new(format!(
+                    "Database alias '{alias}' not found in the configuration"
+                ))
+            })
+    }
+
+    /// Folders of partials, the global one first so the project can overwrite
+    /// its partials. The project folder is relative to the project
+    pub fn partials_dirs(&self) -> Vec<String> {
+        let global = self.global.partials_dir.clone();
+        let project = self
+            .local
+            .as_ref()
+            .and_then(|local| local.partials_dir.as_ref())
+            .map(|dir| Path::new(&self.project).join(dir).display().to_string());
+        global.into_iter().chain(project).collect()
+    }
+
+    /// Names of the profiles of the global and the project configurations
+    pub fn profile_names(&self) -> BTreeSet<String> {
+        self.global
+            .get_profile_names()
+            .into_iter()
+            .chain(self.local.iter().flat_map(YamlConfig::get_profile_names))
+            .collect()
+    }
+
+    /// Looks for the profile in the project first and then in the global
+    /// config, the outputs of a project profile are relative to the project
+    pub fn profile(&self, name: &str) -> Result<YamlConfigProfile, ConfigError> {
+        if let Some(profile) = self
+            .local
+            .as_ref()
+            .and_then(|local| local.get_profile(name))
+        {
+            return Ok(YamlConfigProfile {
+                outputs: profile
+                    .outputs
+                    .into_iter()
+                    .map(|(template, output)| {
+                        let output = Path::new(&self.project).join(output);
+                        (template, output.display().to_string())
+                    })
+                    .collect(),
+                ..profile
+            });
+        }
+        self.global.get_profile(name).ok_or_else(|| {
+            ConfigError::new(format!("Profile '{name}' not found in the configuration"))
+        })
+    }
+
     pub fn add_template(
         &mut self,
         destination: SourceConfig,
         name: Option<String>,
         template: String,
         output: String,
+        variables: Variables,
     ) -> Result<(), ConfigError> {
         match destination {
             SourceConfig::Global => {
-                self.global.add_template(name, template, output);
+                self.global.add_template(name, template, output, variables);
                 Ok(())
             }
             SourceConfig::Project => {
@@ -176,6 +274,7 @@ impl ConfigHandler {
                                 err
                             ))
                         })?,
+                    variables,
                 );
                 self.local = Some(local);
                 Ok(())
@@ -183,6 +282,85 @@ impl ConfigHandler {
         }
     }
 
+    pub fn set_template_vars(
+        &mut self,
+        source: SourceConfig,
+        name: &str,
+        variables: &Variables,
+    ) -> Result<(), ConfigError> {
+        let found = match source {
+            SourceConfig::Global => self.global.set_template_vars(name, variables),
+            SourceConfig::Project => self
+                .local
+                .as_mut()
+                .is_some_and(|local| local.set_template_vars(name, variables)),
+        };
+        if found {
+            Ok(())
+        } else {
+            Err(ConfigError::new(format!(
+                "No template with name '{name}' in the {source} config"
+            )))
+        }
+    }
+
+    /// Variables of the global config overwritten by the ones of the project
+    pub fn get_vars(&self) -> Variables {
+        self.get_vars_with_source()
+            .into_iter()
+            .map(|(key, (value, _))| (key, value))
+            .collect()
+    }
+
+    pub fn get_vars_with_source(&self) -> BTreeMap<String, (Value, SourceConfig)> {
+        let mut variables: BTreeMap<String, (Value, SourceConfig)> = self
+            .global
+            .get_vars()
+            .into_iter()
+            .map(|(key, value)| (key, (value, SourceConfig::Global)))
+            .collect();
+        if let Some(ref local) = self.local {
+            variables.extend(
+                local
+                    .get_vars()
+                    .into_iter()
+                    .map(|(key, value)| (key, (value, SourceConfig::Project))),
+            );
+        }
+        variables
+    }
+
+    /// Required variables of the global config overwritten by the ones of the project
+    pub fn get_required_vars(&self) -> RequiredVariables {
+        let mut required = self.global.get_required_vars();
+        if let Some(ref local) = self.local {
+            required.extend(local.get_required_vars());
+        }
+        required
+    }
+
+    pub fn add_var(&mut self, destination: SourceConfig, var_name: String, value: Value) {
+        match destination {
+            SourceConfig::Global => self.global.add_var(var_name, value),
+            SourceConfig::Project => {
+                let mut local = self.local.clone().unwrap_or_default();
+                local.add_var(var_name, value);
+                self.local = Some(local);
+            }
+        }
+    }
+
+    pub fn del_var(&mut self, source: SourceConfig, var_name: String) {
+        match source {
+            SourceConfig::Global => self.global.del_var(var_name),
+            SourceConfig::Project => {
+                if let Some(ref mut local) = self.local {
+                    local.del_var(var_name);
+                }
+            }
+        }
+    }
+
     pub fn delete_template(&mut self, template: &TemplateInfo) {
         match template.source {
             SourceConfig::Project => {
@@ -220,6 +398,11 @@ impl ConfigHandler {
                 name: template.name.clone(),
                 template: template.template_path.clone(),
                 output: template.output_path.clone(),
+                encrypt: template.encrypt.clone(),
+                hooks: template.hooks.clone(),
+                variables: template.variables.clone(),
+                required_variables: template.required_variables.clone(),
+                escape: template.escape,
             })
             .collect();
         if let Some(ref local) = self.local {
@@ -228,6 +411,18 @@ impl ConfigHandler {
                 name: template.name.clone(),
                 template: format!("{}/{}", self.project, template.template_path.clone()),
                 output: format!("{}/{}", self.project, template.output_path.clone()),
+                encrypt: template.encrypt.clone().map(|encrypt| {
+                    YamlConfigEncrypt {
+                        recipients_file: encrypt
+                            .recipients_file
+                            .map(|file| Path::new(&self.project).join(file).display().to_string()),
+                        ..encrypt
+                    }
+                }),
+                hooks: template.hooks.clone(),
+                variables: template.variables.clone(),
+                required_variables: template.required_variables.clone(),
+                escape: template.escape,
             }));
         }
 
@@ -237,7 +432,14 @@ impl ConfigHandler {
 
 #[cfg(test)]
 mod test {
-    use crate::app::{config::PROJECT_FILE, test_helpers::tests::TestConfig};
+    use std::path::Path;
+
+    use serde_json::Value;
+
+    use crate::app::{
+        config::{PROJECT_FILE, SourceConfig},
+        test_helpers::tests::TestConfig,
+    };
 
     #[test]
     fn test_loading_empty_file() {
@@ -295,4 +497,105 @@ mod test {
                 .ends_with("test_resources/test_db.kdbx")
         )
     }
+
+    #[test]
+    fn test_profile_from_project() {
+        let test_config = TestConfig::create();
+        std::fs::write(
+            format!("{}/{}", test_config.get_project_path(), PROJECT_FILE),
+            "version: 2\nprofiles:\n  staging:\n    group: Staging\n    outputs:\n      valid: staging/.env\n",
+        )
+        .expect("We have a default for the project");
+
+        let config = test_config.get();
+        let profile = config.profile("staging").unwrap();
+
+        assert_eq!(profile.group, Some(String::from("Staging")));
+        assert_eq!(
+            profile.outputs["valid"],
+            Path::new(&test_config.get_project_path())
+                .join("staging/.env")
+                .display()
+                .to_string()
+        );
+        assert_eq!(
+            config.profile("prod").unwrap_err().to_string(),
+            "Profile 'prod' not found in the configuration"
+        );
+    }
+
+    #[test]
+    fn test_project_variables_overwrite_global() {
+        let test_config = TestConfig::create_with_vars();
+        std::fs::write(
+            format!("{}/{}", test_config.get_project_path(), PROJECT_FILE),
+            "version: 2\nvariables:\n  email: project@k.com\n",
+        )
+        .expect("We have a default for the project");
+
+        let config = test_config.get();
+        let variables = config.get_vars_with_source();
+
+        assert_eq!(
+            variables["email"],
+            (Value::from("project@k.com"), SourceConfig::Project)
+        );
+        assert_eq!(
+            variables["something"],
+            (Value::from("is a variable"), SourceConfig::Global)
+        );
+        assert_eq!(config.get_vars()["email"], "project@k.com");
+    }
+
+    #[test]
+    fn test_database_alias() {
+        let test_config = TestConfig::create();
+        std::fs::write(
+            test_config.get_file_path(),
+            "databases:\n  work: /global/work.kdbx\n  personal: /global/personal.kdbx\n",
+        )
+        .unwrap();
+        std::fs::write(
+            format!("{}/{}", test_config.get_project_path(), PROJECT_FILE),
+            "version: 2\ndatabases:\n  work: /project/work.kdbx\n",
+        )
+        .unwrap();
+
+        let config = test_config.get();
+
+        assert_eq!(config.database("work").unwrap(), "/project/work.kdbx");
+        assert_eq!(
+            config.database("personal").unwrap(),
+            "/global/personal.kdbx"
+        );
+        assert_eq!(
+            config.database("other").unwrap_err().to_string(),
+            "Database alias 'other' not found in the configuration"
+        );
+    }
+
+    #[test]
+    fn test_partials_dirs() {
+        let test_config = TestConfig::create();
+        std::fs::write(
+            test_config.get_file_path(),
+            "partials_dir: /global/partials\n",
+        )
+        .unwrap();
+        std::fs::write(
+            format!("{}/{}", test_config.get_project_path(), PROJECT_FILE),
+            "version: 2\npartials_dir: partials\n",
+        )
+        .unwrap();
+
+        let config = test_config.get();
+
+        assert_eq!(
+            config.partials_dirs(),
+            vec![
+                String::from("/global/partials"),
+                format!("{}/partials", test_config.get_project_path())
+            ]
+        );
+    }
 }
@@ -1,13 +1,78 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use serde_yaml::{self};
 
+use crate::app::escaping::EscapeStrategy;
+
+/// Values of the variables, any structure that can be written in yaml or json
+pub type Variables = serde_json::Map<String, Value>;
+
+pub type RequiredVariables = BTreeMap<String, YamlConfigRequiredVariable>;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct YamlConfigRequiredVariable {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// Secret values are asked without echo and never saved in the config
+    #[serde(default)]
+    pub secret: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct YamlConfigEncrypt {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipients_file: Option<String>,
+    #[serde(default)]
+    pub armor: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct YamlConfigHooks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_build: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_build: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_change: Option<String>,
+}
+
+/// Overrides applied when building with `--profile`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct YamlConfigProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keepass: Option<String>,
+    /// Group prepended to the paths of the keepass helper
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Variables::is_empty")]
+    pub variables: Variables,
+    /// Output paths by template name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub outputs: HashMap<String, String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct YamlConfigTemplate {
     pub name: Option<String>,
     pub template_path: String,
     pub output_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypt: Option<YamlConfigEncrypt>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<YamlConfigHooks>,
+    #[serde(default, skip_serializing_if = "Variables::is_empty")]
+    pub variables: Variables,
+    #[serde(default, skip_serializing_if = "RequiredVariables::is_empty")]
+    pub required_variables: RequiredVariables,
+    /// By default it is inferred from the extension of the output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escape: Option<EscapeStrategy>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -17,7 +82,17 @@ pub struct YamlConfig {
     #[serde(default)]
     templates: Vec<YamlConfigTemplate>,
     #[serde(default)]
-    variables: HashMap<String, String>,
+    variables: Variables,
+    #[serde(default, skip_serializing_if = "RequiredVariables::is_empty")]
+    required_variables: RequiredVariables,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    profiles: HashMap<String, YamlConfigProfile>,
+    /// KeePass files by alias, to be used in the front-matter of the templates
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    databases: HashMap<String, String>,
+    /// Folder with the partials shared by the templates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partials_dir: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,7 +102,15 @@ pub struct YamlConfigV1 {
     #[serde(default)]
     templates: Option<Vec<YamlConfigTemplate>>,
     #[serde(default)]
-    variables: Option<HashMap<String, String>>,
+    variables: Option<Variables>,
+    #[serde(default)]
+    required_variables: Option<RequiredVariables>,
+    #[serde(default)]
+    profiles: Option<HashMap<String, YamlConfigProfile>>,
+    #[serde(default)]
+    databases: Option<HashMap<String, String>>,
+    #[serde(default)]
+    partials_dir: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -66,6 +149,7 @@ impl YamlConfig {
         name: Option<String>,
         template_path: String,
         output_path: String,
+        variables: Variables,
     ) {
         let mut templates = self.templates.clone();
         let existing_template = templates
@@ -74,11 +158,17 @@ impl YamlConfig {
 
         if let Some(template) = existing_template {
             template.name = name;
+            template.variables.extend(variables);
         } else {
             templates.push(YamlConfigTemplate {
                 name,
                 template_path,
                 output_path,
+                encrypt: None,
+                hooks: None,
+                variables,
+                required_variables: RequiredVariables::new(),
+                escape: None,
             });
         }
         templates.sort_by(|a, b| match a.template_path.cmp(&b.template_path) {
@@ -109,11 +199,24 @@ impl YamlConfig {
             .collect();
     }
 
-    pub fn get_vars(&self) -> HashMap<String, String> {
+    /// Sets the variables in all the templates with the name, returns false
+    /// when there isn't any
+    pub fn set_template_vars(&mut self, name: &str, variables: &Variables) -> bool {
+        let mut found = false;
+        for template in self.templates.iter_mut() {
+            if template.name.as_deref() == Some(name) {
+                template.variables.extend(variables.clone());
+                found = true;
+            }
+        }
+        found
+    }
+
+    pub fn get_vars(&self) -> Variables {
         self.variables.clone()
     }
 
-    pub fn add_var(&mut self, var_name: String, value: String) {
+    pub fn add_var(&mut self, var_name: String, value: Value) {
         let mut tmp_variables = self.variables.clone();
         tmp_variables.insert(var_name, value);
         self.variables = tmp_variables
@@ -124,6 +227,22 @@ impl YamlConfig {
         tmp_variables.remove(&var_name);
         self.variables = tmp_variables
     }
+
+    pub fn get_required_vars(&self) -> RequiredVariables {
+        self.required_variables.clone()
+    }
+
+    pub fn get_database(&self, alias: &str) -> Option<String> {
+        self.databases.get(alias).cloned()
+    }
+
+    pub fn get_profile(&self, name: &str) -> Option<YamlConfigProfile> {
+        self.profiles.get(name).cloned()
+    }
+
+    pub fn get_profile_names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
 }
 
 impl From<YamlConfigV1> for YamlConfig {
@@ -132,6 +251,190 @@ impl From<YamlConfigV1> for YamlConfig {
             keepass: value.keepass,
             templates: value.templates.unwrap_or_default(),
             variables: value.variables.unwrap_or_default(),
+            required_variables: value.required_variables.unwrap_or_default(),
+            profiles: value.profiles.unwrap_or_default(),
+            databases: value.databases.unwrap_or_default(),
+            partials_dir: value.partials_dir,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_template_encrypt_config() {
+        let config = YamlConfig::new(
+            "version: '2'
+templates:
+- template_path: ./staging.env.hbs
+  output_path: ./staging.env.age
+  encrypt:
+    recipients:
+    - age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p
+    armor: true
+- template_path: ./.env.hbs
+  output_path: ./.env
+",
+        )
+        .unwrap();
+
+        let templates = config.get_templates();
+        let encrypt = templates[0].encrypt.clone().unwrap();
+        assert_eq!(encrypt.recipients.len(), 1);
+        assert_eq!(encrypt.recipients_file, None);
+        assert!(encrypt.armor);
+        assert_eq!(templates[1].encrypt, None);
+
+        let yaml = config.yaml().unwrap();
+        assert!(!yaml.contains("hooks:"));
+        assert_eq!(yaml.matches("encrypt:").count(), 1);
+        assert!(!yaml.contains("recipients_file"));
+    }
+
+    #[test]
+    fn test_template_hooks_config() {
+        let config = YamlConfig::new(
+            "version: '2'
+templates:
+- template_path: ./nginx.conf.hbs
+  output_path: ./nginx.conf
+  hooks:
+    post_build: chmod 600 $KEEPASS_2_FILE_OUTPUT
+    on_change: nginx -s reload
+",
+        )
+        .unwrap();
+
+        let hooks = config.get_templates()[0].hooks.clone().unwrap();
+        assert_eq!(hooks.pre_build, None);
+        assert_eq!(
+            hooks.post_build,
+            Some(String::from("chmod 600 $KEEPASS_2_FILE_OUTPUT"))
+        );
+        assert_eq!(hooks.on_change, Some(String::from("nginx -s reload")));
+    }
+
+    #[test]
+    fn test_profiles_config() {
+        let config = YamlConfig::new(
+            "version: '2'
+profiles:
+  staging:
+    group: Staging
+    variables:
+      host: staging.example.com
+    outputs:
+      api: ./staging/.env
+  prod:
+    keepass: /secure/prod.kdbx
+",
+        )
+        .unwrap();
+
+        let staging = config.get_profile("staging").unwrap();
+        assert_eq!(staging.keepass, None);
+        assert_eq!(staging.group, Some(String::from("Staging")));
+        assert_eq!(staging.variables["host"], "staging.example.com");
+        assert_eq!(staging.outputs["api"], "./staging/.env");
+        assert_eq!(
+            config.get_profile("prod").unwrap().keepass,
+            Some(String::from("/secure/prod.kdbx"))
+        );
+        assert_eq!(config.get_profile("dev"), None);
+
+        let yaml = YamlConfig::default().yaml().unwrap();
+        assert!(!yaml.contains("profiles"));
+    }
+
+    #[test]
+    fn test_template_variables() {
+        let mut config = YamlConfig::default();
+        config.add_template(
+            Some(String::from("api")),
+            String::from("./service.env.hbs"),
+            String::from("./api.env"),
+            Variables::from_iter([(String::from("port"), json!(8080))]),
+        );
+        config.add_template(
+            Some(String::from("worker")),
+            String::from("./service.env.hbs"),
+            String::from("./worker.env"),
+            Variables::new(),
+        );
+
+        assert!(config.set_template_vars(
+            "worker",
+            &Variables::from_iter([(String::from("port"), json!(9090))])
+        ));
+        assert!(!config.set_template_vars("missing", &Variables::new()));
+
+        let templates = config.get_templates();
+        assert_eq!(templates[0].variables["port"], 8080);
+        assert_eq!(templates[1].variables["port"], 9090);
+
+        let yaml = config.yaml().unwrap();
+        assert_eq!(yaml.matches("variables:").count(), 3);
+    }
+
+    #[test]
+    fn test_structured_variables() {
+        let config = YamlConfig::new(
+            "version: '2'
+variables:
+  debug: true
+  port: 8080
+  hosts:
+  - one.example.com
+  - two.example.com
+  database:
+    name: shop
+",
+        )
+        .unwrap();
+
+        let variables = config.get_vars();
+        assert_eq!(variables["debug"], true);
+        assert_eq!(variables["port"], 8080);
+        assert_eq!(
+            variables["hosts"],
+            json!(["one.example.com", "two.example.com"])
+        );
+        assert_eq!(variables["database"]["name"], "shop");
+    }
+
+    #[test]
+    fn test_required_variables_config() {
+        let config = YamlConfig::new(
+            "version: '2'
+required_variables:
+  email:
+    description: E-mail to receive the notifications
+  token:
+    secret: true
+templates:
+- template_path: ./.env.hbs
+  output_path: ./.env
+  required_variables:
+    port:
+      default: 8080
+",
+        )
+        .unwrap();
+
+        let required = config.get_required_vars();
+        assert_eq!(
+            required["email"].description,
+            Some(String::from("E-mail to receive the notifications"))
+        );
+        assert!(!required["email"].secret);
+        assert!(required["token"].secret);
+        assert_eq!(
+            config.get_templates()[0].required_variables["port"].default,
+            Some(json!(8080))
+        );
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    sync::{
+        Arc, Mutex, RwLock, RwLockReadGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use keepass::{
+    Database, DatabaseKey,
+    config::DatabaseVersion,
+    db::{Entry, Value, fields},
+};
+use rand::{Rng, rngs::OsRng};
+
+use super::handlebars::{FieldSelect, extract_entry_field};
+use super::lock::EntryVersion;
+
+const ALPHA: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const NUMERIC: &str = "0123456789";
+const SYMBOLS: &str = "!#$%&()*+,-./:;<=>?@[]^_{|}~";
+
+/// Minor version of KDBX 4 written by the keepass crate
+const KDBX_SAVE_MINOR_VERSION: u16 = 1;
+
+pub const DEFAULT_PASSWORD_LENGTH: usize = 32;
+
+/// Database used by the helpers while rendering, the entries generated are
+/// kept in memory until it is saved
+#[derive(Clone)]
+pub struct SharedDatabase {
+    db: Arc<RwLock<Database>>,
+    writable: bool,
+    modified: Arc<AtomicBool>,
+    /// Entries read by the helpers, by their path
+    accessed: Arc<Mutex<BTreeMap<String, EntryVersion>>>,
+}
+
+impl From<Database> for SharedDatabase {
+    fn from(db: Database) -> Self {
+        Self::new(db, false)
+    }
+}
+
+impl SharedDatabase {
+    pub fn new(db: Database, writable: bool) -> Self {
+        Self {
+            db: Arc::new(RwLock::new(db)),
+            writable,
+            modified: Arc::new(AtomicBool::new(false)),
+            accessed: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, Database> {
+        self.db.read().unwrap()
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified.load(Ordering::SeqCst)
+    }
+
+    pub fn has_entry(&self, path: &[String]) -> bool {
+        let Some((title, groups)) = path.split_last() else {
+            return false;
+        };
+        let groups: Vec<&str> = groups.iter().map(String::as_str).collect();
+        self.read()
+            .root()
+            .group_by_path(&groups)
+            .is_some_and(|group| group.entry_by_name(title).is_some())
+    }
+
+    /// Version of the entry of the path, with the groups separated by slashes
+    pub fn entry_version(&self, path: &str) -> Option<EntryVersion> {
+        let path: Vec<&str> = path.split('/').collect();
+        let (title, groups) = path.split_last()?;
+        let db = self.read();
+        let root = db.root();
+        let group = root.group_by_path(groups)?;
+        let entry = group.entry_by_name(title)?;
+        Some(EntryVersion::from(&*entry))
+    }
+
+    pub fn record_access(&self, path: &[String], entry: &Entry) {
+        self.accessed
+            .lock()
+            .unwrap()
+            .insert(path.join("/"), EntryVersion::from(entry));
+    }
+
+    /// Entries read since the previous call
+    pub fn take_accessed(&self) -> BTreeMap<String, EntryVersion> {
+        std::mem::take(&mut *self.accessed.lock().unwrap())
+    }
+
+    pub fn read_field(&self, path: &[String], field: &FieldSelect) -> Option<String> {
+        let (title, groups) = path.split_last()?;
+        let groups: Vec<&str> = groups.iter().map(String::as_str).collect();
+        let db = self.read();
+        let root = db.root();
+        let group = root.group_by_path(&groups)?;
+        let entry = group.entry_by_name(title)?;
+        extract_entry_field(&entry, path.to_vec(), field.clone()).ok()
+    }
+
+    /// Changes the value of a field, the previous version of the entry is kept
+    /// in its history. The field keeps its protection, and a new one is
+    /// protected when it is the password or the field is protected in other
+    /// entries
+    pub fn update_field(&self, path: &[String], field: &FieldSelect, value: &str) -> bool {
+        let Some((title, groups)) = path.split_last() else {
+            return false;
+        };
+        let groups: Vec<&str> = groups.iter().map(String::as_str).collect();
+        let key = field.key();
+        let mut db = self.db.write().unwrap();
+        let protected_elsewhere = matches!(field, FieldSelect::Password)
+            || db
+                .iter_all_entries()
+                .any(|entry| entry.fields.get(key).is_some_and(Value::is_protected));
+        let mut root = db.root_mut();
+        let Some(mut group) = root.group_by_path_mut(&groups) else {
+            return false;
+        };
+        let Some(mut entry) = group.entry_by_name_mut(title) else {
+            return false;
+        };
+        let protected = entry
+            .fields
+            .get(key)
+            .map_or(protected_elsewhere, Value::is_protected);
+        entry.edit_tracking(|entry| {
+            if protected {
+                entry.set_protected(key, value)
+            } else {
+                entry.set_unprotected(key, value)
+            }
+        });
+        self.modified.store(true, Ordering::SeqCst);
+        true
+    }
+
+    /// Adds an entry with the password, creating the groups of the path
+    /// that don't exist
+    pub fn add_entry(&self, path: &[String], password: &str) {
+        let Some((title, groups)) = path.split_last() else {
+            return;
+        };
+        let mut db = self.db.write().unwrap();
+        let mut group_id = db.root().id();
+        for name in groups {
+            let existing = db
+                .group(group_id)
+                .and_then(|group| group.group_by_name(name).map(|group| group.id()));
+            group_id = match existing {
+                Some(id) => id,
+                None => {
+                    let mut parent = db.group_mut(group_id).expect("The parent group exists");
+                    let mut group = parent.add_group();
+                    group.name = name.clone();
+                    group.id()
+                }
+            };
+        }
+        db.group_mut(group_id)
+            .expect("The group exists")
+            .add_entry()
+            .edit(|entry| {
+                entry.set_unprotected(fields::TITLE, title.as_str());
+                entry.set_protected(fields::PASSWORD, password);
+            });
+        self.modified.store(true, Ordering::SeqCst);
+    }
+
+    /// Version the database was opened with, when it is not the one written
+    /// by save
+    pub fn upgraded_version(&self) -> Option<DatabaseVersion> {
+        let version = &self.read().config.version;
+        match version {
+            DatabaseVersion::KDB4(_) => None,
+            _ => Some(version.clone()),
+        }
+    }
+
+    /// Copies the current file to a backup before writing the database,
+    /// returns the path of the backup. The file is written as KDBX 4.1, the
+    /// only version that can be saved, whatever the version it was opened with.
+    /// It is written next to the database with its permissions and renamed
+    /// over it, to never leave a partially written database
+    pub fn save(&self, path: &str, password: &str) -> Result<String, Box<dyn Error>> {
+        let mut db = self.read().clone();
+        db.config.version = DatabaseVersion::KDB4(KDBX_SAVE_MINOR_VERSION);
+        let mut contents = Vec::new();
+        let key = DatabaseKey::new().with_password(password);
+        db.save(&mut contents, key)
+            .map_err(|e| format!("Cannot save the database {path}: {e}"))?;
+
+        let stem = backup_stem(path);
+        let backup = format!("{stem}.bak");
+        std::fs::copy(path, &backup)
+            .map_err(|e| format!("Cannot create the backup {backup}: {e}"))?;
+        let permissions = std::fs::metadata(path)
+            .map_err(|e| format!("Cannot read the database {path}: {e}"))?
+            .permissions();
+        let tmp = format!("{stem}.tmp");
+        std::fs::write(&tmp, contents)
+            .and_then(|_| std::fs::set_permissions(&tmp, permissions))
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .map_err(|e| {
+                let _ = std::fs::remove_file(&tmp);
+                format!("Cannot write the database {path}: {e}")
+            })?;
+        self.modified.store(false, Ordering::SeqCst);
+        Ok(backup)
+    }
+}
+
+/// Name of the backup without its extension, like db.kdbx.1700000000, with a
+/// counter when a backup of the same second exists
+fn backup_stem(path: &str) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let mut stem = format!("{path}.{timestamp}");
+    let mut counter = 1;
+    while std::path::Path::new(&format!("{stem}.bak")).exists() {
+        stem = format!("{path}.{timestamp}-{counter}");
+        counter += 1;
+    }
+    stem
+}
+
+/// Charsets: alnum, alpha, numeric, hex and ascii (alnum with symbols)
+pub fn generate_password(length: usize, charset: &str) -> Result<String, String> {
+    let chars: Vec<char> = match charset {
+        "alnum" => format!("{ALPHA}{NUMERIC}").chars().collect(),
+        "alpha" => ALPHA.chars().collect(),
+        "numeric" => NUMERIC.chars().collect(),
+        "hex" => "0123456789abcdef".chars().collect(),
+        "ascii" => format!("{ALPHA}{NUMERIC}{SYMBOLS}").chars().collect(),
+        _ => return Err(format!("Unknown charset '{charset}'")),
+    };
+    let mut rng = OsRng;
+    Ok((0..length)
+        .map(|_| chars[rng.gen_range(0..chars.len())])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_helpers::TmpFile;
+
+    use super::*;
+    use crate::app::errors_and_warnings::HelperErrors;
+    use crate::app::handlebars::build_handlebars;
+    use crate::app::test_helpers::tests::get_test_db;
+
+    #[test]
+    fn test_update_field_keeps_history() {
+        let database = SharedDatabase::new(get_test_db(), true);
+        let path = vec![String::from("group1"), String::from("test2")];
+
+        assert!(database.update_field(&path, &FieldSelect::Password, "rotated"));
+        assert!(!database.update_field(&[String::from("unknown")], &FieldSelect::Password, "x"));
+
+        assert!(database.is_modified());
+        assert_eq!(
+            database.read_field(&path, &FieldSelect::Password),
+            Some(String::from("rotated"))
+        );
+        let db = database.read();
+        let root = db.root();
+        let group = root.group_by_path(&["group1"]).unwrap();
+        let entry = group.entry_by_name("test2").unwrap();
+        let history = entry.history.as_ref().unwrap().get_entries();
+        assert_eq!(history.last().unwrap().get_password(), Some("testing"));
+    }
+
+    #[test]
+    fn test_update_field_keeps_protection() {
+        let mut db = get_test_db();
+        let mut root = db.root_mut();
+        let mut complex = root.entry_by_name_mut("complex").unwrap();
+        complex.set_protected("api-key", "secret");
+        complex.set_unprotected("region", "eu");
+        let database = SharedDatabase::new(db, true);
+        let complex = vec![String::from("complex")];
+        let test2 = vec![String::from("group1"), String::from("test2")];
+        let field = |name: &str| FieldSelect::AdditionalAttributes {
+            field_name: name.to_string(),
+        };
+
+        assert!(database.update_field(&complex, &field("api-key"), "rotated"));
+        assert!(database.update_field(&complex, &field("region"), "us"));
+        assert!(database.update_field(&test2, &field("api-key"), "new"));
+        assert!(database.update_field(&test2, &field("region"), "us"));
+        assert!(database.update_field(&test2, &FieldSelect::Username, "user"));
+
+        let db = database.read();
+        let root = db.root();
+        let protected = |entry: &Entry, name: &str| entry.fields[name].is_protected();
+        let complex = root.entry_by_name("complex").unwrap();
+        assert!(protected(&complex, "api-key"));
+        assert!(!protected(&complex, "region"));
+        let group = root.group_by_path(&["group1"]).unwrap();
+        let test2 = group.entry_by_name("test2").unwrap();
+        assert!(protected(&test2, "api-key"));
+        assert!(!protected(&test2, "region"));
+        assert!(!protected(&test2, fields::USERNAME));
+        assert!(protected(&test2, fields::PASSWORD));
+    }
+
+    #[test]
+    fn test_save() {
+        let file = TmpFile::new_uuid("test_resources/tmp", Some("kdbx"));
+        std::fs::copy("test_resources/test_db.kdbx", file.get()).unwrap();
+        let mut db = get_test_db();
+        db.config.version = DatabaseVersion::KDB3(1);
+        let database = SharedDatabase::new(db, true);
+        let path = vec![String::from("group1"), String::from("test2")];
+        database.update_field(&path, &FieldSelect::Password, "rotated");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = std::fs::Permissions::from_mode(0o600);
+            std::fs::set_permissions(file.get(), permissions).unwrap();
+        }
+
+        assert_eq!(database.upgraded_version(), Some(DatabaseVersion::KDB3(1)));
+        let backup = database.save(&file.get(), "MyTestPass").unwrap();
+        let second_backup = database.save(&file.get(), "MyTestPass").unwrap();
+        assert_ne!(backup, second_backup);
+        std::fs::remove_file(&backup).unwrap();
+        std::fs::remove_file(&second_backup).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(file.get()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let key = DatabaseKey::new().with_password("MyTestPass");
+        let saved = Database::open(&mut std::fs::File::open(file.get()).unwrap(), key).unwrap();
+        assert_eq!(saved.config.version, DatabaseVersion::KDB4(1));
+        let saved = SharedDatabase::from(saved);
+        assert_eq!(saved.upgraded_version(), None);
+        assert_eq!(
+            saved.read_field(&path, &FieldSelect::Password),
+            Some(String::from("rotated"))
+        );
+        let tmp = backup.replace(".bak", ".tmp");
+        assert!(!std::path::Path::new(&tmp).exists());
+    }
+
+    #[test]
+    fn test_accessed_entries() {
+        let database = SharedDatabase::from(get_test_db());
+        let errors = HelperErrors::new();
+        let handlebars = build_handlebars(database.clone(), None, &errors);
+
+        handlebars
+            .render_template(
+                "{{keepass \"test1\"}} {{keepass \"group1/test2\" field=\"username\"}} {{keepass \"unknown\"}}",
+                &(),
+            )
+            .unwrap();
+
+        let accessed = database.take_accessed();
+        assert_eq!(
+            accessed.keys().collect::<Vec<&String>>(),
+            vec!["group1/test2", "test1"]
+        );
+        assert_eq!(
+            accessed.get("test1"),
+            database.entry_version("test1").as_ref()
+        );
+        assert!(database.entry_version("group1/unknown").is_none());
+        assert!(database.take_accessed().is_empty());
+    }
+
+    #[test]
+    fn test_generate_password() {
+        let password = generate_password(20, "numeric").unwrap();
+        assert_eq!(password.len(), 20);
+        assert!(password.chars().all(|c| c.is_ascii_digit()));
+
+        let password = generate_password(DEFAULT_PASSWORD_LENGTH, "alnum").unwrap();
+        assert_eq!(password.len(), DEFAULT_PASSWORD_LENGTH);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+
+        assert_eq!(
+            generate_password(8, "unknown").unwrap_err(),
+            "Unknown charset 'unknown'"
+        );
+    }
+}
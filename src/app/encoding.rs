@@ -0,0 +1,219 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bcrypt::{DEFAULT_COST, Version};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, JsonRender, JsonValue, RenderContext, RenderError,
+    RenderErrorReason, ScopedJson,
+};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use super::handlebars::LibHandlebars;
+
+type HelperFn = fn(&Helper) -> Result<String, RenderError>;
+
+/// Helpers that derive a value from their parameters, the result is escaped
+/// like the rest of the values of the template
+struct DerivedHelper(HelperFn);
+
+impl HelperDef for DerivedHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        Ok(ScopedJson::Derived(JsonValue::from((self.0)(h)?)))
+    }
+}
+
+fn helper_error(message: String) -> RenderError {
+    RenderErrorReason::Other(message).into()
+}
+
+fn param(h: &Helper, index: usize) -> Result<String, RenderError> {
+    h.param(index)
+        .map(|param| param.value().render())
+        .ok_or_else(|| {
+            helper_error(format!(
+                "Missing parameter {} of the {} helper",
+                index + 1,
+                h.name()
+            ))
+        })
+}
+
+fn cost(h: &Helper) -> Result<u32, RenderError> {
+    match h.hash_get("cost") {
+        Some(cost) => cost
+            .value()
+            .as_u64()
+            .map(|cost| cost as u32)
+            .ok_or_else(|| helper_error(format!("The cost of {} must be a number", h.name()))),
+        None => Ok(DEFAULT_COST),
+    }
+}
+
+/// All the parameters are joined, to encode values like user:password
+fn base64_helper(h: &Helper) -> Result<String, RenderError> {
+    param(h, 0)?;
+    let value: String = h
+        .params()
+        .iter()
+        .map(|param| param.value().render())
+        .collect();
+    Ok(STANDARD.encode(value))
+}
+
+fn base64_decode_helper(h: &Helper) -> Result<String, RenderError> {
+    let bytes = STANDARD
+        .decode(param(h, 0)?)
+        .map_err(|e| helper_error(format!("Invalid base64 value: {e}")))?;
+    String::from_utf8(bytes)
+        .map_err(|_| helper_error(String::from("The decoded value is not text")))
+}
+
+/// Encodes everything except the unreserved characters of RFC 3986
+pub fn urlencode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn urlencode_helper(h: &Helper) -> Result<String, RenderError> {
+    Ok(urlencode(&param(h, 0)?))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn sha256_helper(h: &Helper) -> Result<String, RenderError> {
+    Ok(to_hex(&Sha256::digest(param(h, 0)?)))
+}
+
+fn hmac_sha256_helper(h: &Helper) -> Result<String, RenderError> {
+    let message = param(h, 0)?;
+    let key = h
+        .hash_get("key")
+        .map(|key| key.value().render())
+        .ok_or_else(|| helper_error(String::from("The hmac_sha256 helper requires a key")))?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+        .map_err(|e| helper_error(format!("Invalid hmac key: {e}")))?;
+    mac.update(message.as_bytes());
+    Ok(to_hex(&mac.finalize().into_bytes()))
+}
+
+fn bcrypt_helper(h: &Helper) -> Result<String, RenderError> {
+    bcrypt::hash(param(h, 0)?, cost(h)?).map_err(|e| helper_error(format!("bcrypt failed: {e}")))
+}
+
+/// Line of an htpasswd file, with the $2y$ bcrypt format used by apache
+fn htpasswd_helper(h: &Helper) -> Result<String, RenderError> {
+    let user = param(h, 0)?;
+    let password = param(h, 1)?;
+    let hash = bcrypt::hash_with_result(password, cost(h)?)
+        .map_err(|e| helper_error(format!("bcrypt failed: {e}")))?;
+    Ok(format!("{user}:{}", hash.format_for_version(Version::TwoY)))
+}
+
+const ENCODING_HELPERS: [(&str, HelperFn); 7] = [
+    ("base64", base64_helper),
+    ("base64_decode", base64_decode_helper),
+    ("urlencode", urlencode_helper),
+    ("sha256", sha256_helper),
+    ("hmac_sha256", hmac_sha256_helper),
+    ("bcrypt", bcrypt_helper),
+    ("htpasswd", htpasswd_helper),
+];
+
+pub fn register_encoding_helpers(handlebars: &mut LibHandlebars) {
+    for (name, helper) in ENCODING_HELPERS {
+        handlebars.register_helper(name, Box::new(DerivedHelper(helper)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::errors_and_warnings::HelperErrors;
+    use crate::app::handlebars::build_handlebars;
+    use crate::app::test_helpers::tests::get_test_db;
+
+    fn render(template: &str) -> Result<String, String> {
+        let errors = HelperErrors::new();
+        let handlebars = build_handlebars(get_test_db(), None, &errors);
+        handlebars
+            .render_template(template, &())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(
+            render("{{base64 (keepass \"group1/test2\")}}").unwrap(),
+            "dGVzdGluZw=="
+        );
+        assert_eq!(
+            render(
+                "{{base64 (keepass \"group1/test2\" field=\"username\") \":\" (keepass \"group1/test2\")}}"
+            )
+            .unwrap(),
+            "dXNlcjI6dGVzdGluZw=="
+        );
+        assert_eq!(
+            render("{{base64_decode (base64 (keepass \"test1\"))}}").unwrap(),
+            "8/k,9P`Y\"\"7)*]CNdM~,"
+        );
+        assert!(
+            render("{{base64_decode \"not base64!\"}}")
+                .unwrap_err()
+                .contains("Invalid base64 value")
+        );
+    }
+
+    #[test]
+    fn test_urlencode() {
+        assert_eq!(
+            render("{{urlencode (keepass \"test1\")}}").unwrap(),
+            "8%2Fk%2C9P%60Y%22%227%29%2A%5DCNdM~%2C"
+        );
+    }
+
+    #[test]
+    fn test_digests() {
+        assert_eq!(
+            render("{{sha256 (keepass \"group1/test2\")}}").unwrap(),
+            "cf80cd8aed482d5d1527d7dc72fceff84e6326592848447d2dc0b0e87dfc9a90"
+        );
+        assert_eq!(
+            render("{{hmac_sha256 (keepass \"group1/test2\") key=(keepass \"complex\")}}").unwrap(),
+            "5948ce13a2ac20ac1817fcc283f96fc3fbf009727b1142aeb82684c9dc3c91db"
+        );
+        assert!(
+            render("{{hmac_sha256 \"value\"}}")
+                .unwrap_err()
+                .contains("The hmac_sha256 helper requires a key")
+        );
+    }
+
+    #[test]
+    fn test_bcrypt_and_htpasswd() {
+        let hash = render("{{bcrypt (keepass \"group1/test3\") cost=4}}").unwrap();
+        assert!(bcrypt::verify("X^|5uHs3uFKds<CS~\\GC", &hash).unwrap());
+
+        let line = render(
+            "{{htpasswd (keepass \"group1/test3\" field=\"username\") (keepass \"group1/test3\") cost=4}}",
+        )
+        .unwrap();
+        let (user, hash) = line.split_once(':').unwrap();
+        assert_eq!(user, "user3");
+        assert!(hash.starts_with("$2y$04$"));
+        assert!(bcrypt::verify("X^|5uHs3uFKds<CS~\\GC", hash).unwrap());
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    error::Error,
+    io::{Read, Write},
+};
+
+use age::{
+    Decryptor, Encryptor, IdentityFile,
+    armor::{ArmoredReader, ArmoredWriter, Format},
+    x25519,
+};
+
+use super::config::yaml::YamlConfigEncrypt;
+
+fn parse_recipient(recipient: &str) -> Result<x25519::Recipient, Box<dyn Error>> {
+    recipient
+        .trim()
+        .parse::<x25519::Recipient>()
+        .map_err(|e| format!("Invalid age recipient '{recipient}': {e}").into())
+}
+
+fn load_recipients(settings: &YamlConfigEncrypt) -> Result<Vec<x25519::Recipient>, Box<dyn Error>> {
+    let mut recipients = settings
+        .recipients
+        .iter()
+        .map(|recipient| parse_recipient(recipient))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(recipients_file) = &settings.recipients_file {
+        let contents = std::fs::read_to_string(recipients_file)
+            .map_err(|e| format!("Failed to read recipients file {recipients_file}: {e}"))?;
+        for line in contents.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                recipients.push(parse_recipient(line)?);
+            }
+        }
+    }
+
+    if recipients.is_empty() {
+        return Err("The encryption is enabled but no recipients are configured".into());
+    }
+    Ok(recipients)
+}
+
+pub fn encrypt(content: &[u8], settings: &YamlConfigEncrypt) -> Result<Vec<u8>, Box<dyn Error>> {
+    let recipients = load_recipients(settings)?;
+    let encryptor = Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| recipient as &dyn age::Recipient),
+    )?;
+
+    let format = if settings.armor {
+        Format::AsciiArmor
+    } else {
+        Format::Binary
+    };
+    let mut encrypted = vec![];
+    let armored = ArmoredWriter::wrap_output(&mut encrypted, format)?;
+    let mut writer = encryptor.wrap_output(armored)?;
+    writer.write_all(content)?;
+    writer.finish()?.finish()?;
+
+    Ok(encrypted)
+}
+
+/// Decrypts binary or armored age content with the identities of an age identity file
+pub fn decrypt(content: &[u8], identity_file: String) -> Result<Vec<u8>, Box<dyn Error>> {
+    let identities = IdentityFile::from_file(identity_file.clone())
+        .map_err(|e| format!("Failed to read identity file {identity_file}: {e}"))?
+        .into_identities()?;
+
+    let decryptor = Decryptor::new(ArmoredReader::new(content))?;
+    let mut reader = decryptor.decrypt(identities.iter().map(|identity| identity.as_ref()))?;
+
+    let mut decrypted = vec![];
+    reader.read_to_end(&mut decrypted)?;
+    Ok(decrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use age::secrecy::ExposeSecret;
+    use test_helpers::TmpFile;
+
+    use super::*;
+
+    fn identity_file(identity: &x25519::Identity) -> TmpFile {
+        let file = TmpFile::new_uuid("test_resources/tmp", Some("txt"));
+        file.write(format!("{}\n", identity.to_string().expose_secret()));
+        file
+    }
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let identity = x25519::Identity::generate();
+        let settings = YamlConfigEncrypt {
+            recipients: vec![identity.to_public().to_string()],
+            ..YamlConfigEncrypt::default()
+        };
+
+        let encrypted = encrypt(b"SECRET=value", &settings).unwrap();
+        assert!(encrypted.starts_with(b"age-encryption.org/v1"));
+
+        let file = identity_file(&identity);
+        let decrypted = decrypt(&encrypted, file.get()).unwrap();
+        assert_eq!(decrypted, b"SECRET=value");
+    }
+
+    #[test]
+    fn test_encrypt_armored_with_recipients_file() {
+        let identity = x25519::Identity::generate();
+        let recipients = TmpFile::new_uuid("test_resources/tmp", Some("txt"));
+        recipients.write(format!("# staging box\n{}\n\n", identity.to_public()));
+        let settings = YamlConfigEncrypt {
+            recipients_file: Some(recipients.get()),
+            armor: true,
+            ..YamlConfigEncrypt::default()
+        };
+
+        let encrypted = encrypt(b"SECRET=value", &settings).unwrap();
+        assert!(encrypted.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----"));
+
+        let file = identity_file(&identity);
+        let decrypted = decrypt(&encrypted, file.get()).unwrap();
+        assert_eq!(decrypted, b"SECRET=value");
+    }
+
+    #[test]
+    fn test_encrypt_invalid_recipient() {
+        let settings = YamlConfigEncrypt {
+            recipients: vec![String::from("not-a-recipient")],
+            ..YamlConfigEncrypt::default()
+        };
+
+        let result = encrypt(b"SECRET=value", &settings);
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .starts_with("Invalid age recipient 'not-a-recipient'")
+        );
+    }
+
+    #[test]
+    fn test_encrypt_without_recipients() {
+        let result = encrypt(b"SECRET=value", &YamlConfigEncrypt::default());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The encryption is enabled but no recipients are configured"
+        );
+    }
+}
@@ -1,7 +1,15 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::front_matter::{front_matter_lines, split_front_matter};
 
 pub trait ErrorRecord: Send + Sync {
-    fn register_error(&self, code: ErrorCode);
+    fn register_error(&self, code: ErrorCode, location: Option<ErrorLocation>);
+    /// Times a call with the same key was rendered before, to tell apart the
+    /// identical calls of a template
+    fn count_call(&self, key: String) -> usize;
 }
 
 #[derive(Clone, Debug)]
@@ -9,39 +17,139 @@ pub enum ErrorCode {
     MissingPath,
     GroupFound(Vec<String>),
     MissingEntry(Vec<String>),
+    /// The entry is missing and cannot be generated without --allow-db-write
+    WriteNotAllowed(Vec<String>),
     MissingField(Vec<String>, String),
     NoPassword(Vec<String>),
     NoUsername(Vec<String>),
     NoUrl(Vec<String>),
     DeprecatedPathFormat(Vec<String>),
+    DuplicatedKey(Vec<String>, String),
+}
+
+/// Position of the expression that produced an error, the line is relative to
+/// the template registered, without its front-matter
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorLocation {
+    pub template: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl ErrorLocation {
+    /// Number and text of the line in the file of the template. The partials
+    /// of a folder are registered by name, so their source is not found
+    fn source_line(&self) -> Option<(usize, String)> {
+        let source = std::fs::read_to_string(&self.template).ok()?;
+        let (_, body) = split_front_matter(&source).ok()?;
+        let line = self.line + front_matter_lines(&source, body);
+        let text = source.lines().nth(line.checked_sub(1)?)?;
+        Some((line, text.to_string()))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HelperError {
+    pub code: ErrorCode,
+    pub location: Option<ErrorLocation>,
+}
+
+/// Formats the message like a compiler, with the position in the template and
+/// the line marking the column when the template can be read
+pub fn format_error(label: &str, message: &str, location: Option<&ErrorLocation>) -> String {
+    let Some(location) = location else {
+        return format!("{label}: {message}");
+    };
+    let template = &location.template;
+    let Some((line, text)) = location.source_line() else {
+        let (line, column) = (location.line, location.column);
+        return format!("{label}: {message}\n --> {template}:{line}:{column}");
+    };
+    let margin = " ".repeat(line.to_string().len());
+    let marker = " ".repeat(location.column.saturating_sub(1));
+    format!(
+        "{label}: {message}\n{margin}--> {template}:{line}:{}\n{margin} |\n{line} | {text}\n{margin} | {marker}^",
+        location.column
+    )
 }
 
 #[derive(Clone)]
 pub struct HelperErrors {
-    errors: Arc<Mutex<Vec<ErrorCode>>>,
+    errors: Arc<Mutex<Vec<HelperError>>>,
+    /// Calls rendered since the last clean, by their key
+    calls: Arc<Mutex<HashMap<String, usize>>>,
 }
 
 impl HelperErrors {
     pub fn new() -> Self {
         Self {
             errors: Arc::new(Mutex::new(vec![])),
+            calls: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
     pub fn clean(&mut self) {
         let mut errors = self.errors.lock().unwrap();
         errors.clear();
+        self.calls.lock().unwrap().clear();
     }
 
-    pub fn get_errors(&self) -> Vec<ErrorCode> {
+    pub fn get_errors(&self) -> Vec<HelperError> {
         let errors = self.errors.lock().unwrap();
         errors.clone()
     }
 }
 
 impl ErrorRecord for HelperErrors {
-    fn register_error(&self, code: ErrorCode) {
+    fn register_error(&self, code: ErrorCode, location: Option<ErrorLocation>) {
         let mut errors = self.errors.lock().unwrap();
-        errors.push(code);
+        errors.push(HelperError { code, location });
+    }
+
+    fn count_call(&self, key: String) -> usize {
+        let mut calls = self.calls.lock().unwrap();
+        let count = calls.entry(key).or_default();
+        *count += 1;
+        *count - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_helpers::TmpFile;
+
+    use super::*;
+
+    #[test]
+    fn test_format_error() {
+        let template = TmpFile::new_uuid("test_resources/tmp", Some("hbs"));
+        template.write(String::from(
+            "---\noutput: .env\n---\nA=1\nB={{keepass \"unknown\"}}\n",
+        ));
+        let location = |template: String| ErrorLocation {
+            template,
+            line: 2,
+            column: 3,
+        };
+
+        assert_eq!(
+            format_error("error", "Entry not found", Some(&location(template.get()))),
+            format!(
+                "error: Entry not found\n --> {}:5:3\n  |\n5 | B={{{{keepass \"unknown\"}}}}\n  |   ^",
+                template.get()
+            )
+        );
+        assert_eq!(
+            format_error(
+                "error",
+                "Entry not found",
+                Some(&location(String::from("header")))
+            ),
+            "error: Entry not found\n --> header:2:3"
+        );
+        assert_eq!(
+            format_error("warning", "Deprecated", None),
+            "warning: Deprecated"
+        );
     }
 }
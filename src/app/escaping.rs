@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// How the values written in a template are escaped. All of them, except
+/// shell, escape the value to be written inside double quotes
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EscapeStrategy {
+    Json,
+    Yaml,
+    Toml,
+    Xml,
+    /// Quotes the value with single quotes
+    Shell,
+    Dotenv,
+    None,
+}
+
+impl EscapeStrategy {
+    /// Only the structured formats are inferred, the rest of the outputs keep
+    /// the values as they are
+    pub fn from_output(output: &str) -> Self {
+        let extension = Path::new(output)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("json") => Self::Json,
+            Some("yaml") | Some("yml") => Self::Yaml,
+            Some("toml") => Self::Toml,
+            Some("xml") => Self::Xml,
+            _ => Self::None,
+        }
+    }
+
+    pub fn escape(&self, value: &str) -> String {
+        match self {
+            Self::Json => json_escape(value),
+            Self::Yaml => yaml_escape(value),
+            Self::Toml => toml_escape(value),
+            Self::Xml => xml_escape(value),
+            Self::Shell => shell_quote(value),
+            Self::Dotenv => dotenv_escape(value),
+            Self::None => value.to_string(),
+        }
+    }
+}
+
+pub fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// The escapes of json are valid inside the double quoted scalars of yaml
+pub fn yaml_escape(value: &str) -> String {
+    json_escape(value)
+}
+
+pub fn toml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Escaping inside double quotes, multi-line values are kept in a single line
+pub fn dotenv_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_output() {
+        assert_eq!(
+            EscapeStrategy::from_output("config.json"),
+            EscapeStrategy::Json
+        );
+        assert_eq!(
+            EscapeStrategy::from_output("values.YML"),
+            EscapeStrategy::Yaml
+        );
+        assert_eq!(
+            EscapeStrategy::from_output("a/b.toml"),
+            EscapeStrategy::Toml
+        );
+        assert_eq!(EscapeStrategy::from_output("pom.xml"), EscapeStrategy::Xml);
+        assert_eq!(EscapeStrategy::from_output(".env"), EscapeStrategy::None);
+        assert_eq!(EscapeStrategy::from_output("run.sh"), EscapeStrategy::None);
+    }
+
+    #[test]
+    fn test_escape_strategies() {
+        let value = "a\"b\\c'd$e<f>\ng\u{7f}";
+
+        assert_eq!(
+            EscapeStrategy::Json.escape(value),
+            "a\\\"b\\\\c'd$e<f>\\ng\u{7f}"
+        );
+        assert_eq!(
+            EscapeStrategy::Yaml.escape(value),
+            EscapeStrategy::Json.escape(value)
+        );
+        assert_eq!(
+            EscapeStrategy::Toml.escape(value),
+            "a\\\"b\\\\c'd$e<f>\\ng\\u007F"
+        );
+        assert_eq!(
+            EscapeStrategy::Xml.escape(value),
+            "a&quot;b\\c&apos;d$e&lt;f&gt;\ng\u{7f}"
+        );
+        assert_eq!(
+            EscapeStrategy::Shell.escape(value),
+            "'a\"b\\c'\\''d$e<f>\ng\u{7f}'"
+        );
+        assert_eq!(
+            EscapeStrategy::Dotenv.escape(value),
+            "a\\\"b\\\\c'd\\$e<f>\\ng\u{7f}"
+        );
+        assert_eq!(EscapeStrategy::None.escape(value), value);
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    error::Error,
+};
+
+use clap::ValueEnum;
+use keepass::Database;
+
+use super::errors_and_warnings::{ErrorCode, ErrorRecord};
+use super::handlebars::{FieldSelect, extract_entry_field, quote_dotenv};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    Dotenv,
+    Json,
+    Yaml,
+    Toml,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum KeyNaming {
+    /// SOME_WEIRD_NAME for an entry titled "Some weird name"
+    UpperSnake,
+    /// Use the entry title as it is
+    Title,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub naming: KeyNaming,
+    pub key_attribute: Option<String>,
+    pub prefix: String,
+    pub fields: Vec<String>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            naming: KeyNaming::UpperSnake,
+            key_attribute: None,
+            prefix: String::new(),
+            fields: vec![String::from("password")],
+        }
+    }
+}
+
+pub fn split_group_path(group: &str) -> Vec<String> {
+    group
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+pub fn to_upper_snake(name: &str) -> String {
+    let mut result = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            if c.is_uppercase() && previous_lower {
+                result.push('_');
+            }
+            result.extend(c.to_uppercase());
+            previous_lower = c.is_lowercase() || c.is_numeric();
+        } else {
+            if !result.is_empty() && !result.ends_with('_') {
+                result.push('_');
+            }
+            previous_lower = false;
+        }
+    }
+    result.trim_end_matches('_').to_string()
+}
+
+fn apply_naming(name: &str, naming: KeyNaming) -> String {
+    match naming {
+        KeyNaming::UpperSnake => to_upper_snake(name),
+        KeyNaming::Title => name.to_string(),
+    }
+}
+
+/// Reads the selected fields of every entry directly inside `group`, the keys
+/// are sorted so the output is the same between runs
+pub fn collect_group(
+    db: &Database,
+    group: &str,
+    options: &ExportOptions,
+    errors: &dyn ErrorRecord,
+) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let group_path = split_group_path(group);
+    let group_path_ref: Vec<&str> = group_path.iter().map(|x| x.as_str()).collect();
+    let root = db.root();
+    let Some(group_ref) = root.group_by_path(&group_path_ref) else {
+        return Err(format!("Group '{group}' not found in the KeePass database").into());
+    };
+
+    let mut values = BTreeMap::new();
+    for entry in group_ref.entries() {
+        let Some(title) = entry.get_title() else {
+            continue;
+        };
+        let mut entry_path = group_path.clone();
+        entry_path.push(title.to_string());
+
+        let base_name = match options
+            .key_attribute
+            .as_ref()
+            .and_then(|attribute| entry.get(attribute))
+        {
+            Some(custom_name) => custom_name.to_string(),
+            None => apply_naming(title, options.naming),
+        };
+
+        for field in &options.fields {
+            let key = if options.fields.len() == 1 {
+                format!("{}{}", options.prefix, base_name)
+            } else {
+                format!(
+                    "{}{}_{}",
+                    options.prefix,
+                    base_name,
+                    apply_naming(field, options.naming)
+                )
+            };
+            let field_select = FieldSelect::from_name(field.clone());
+            match extract_entry_field(&entry, entry_path.clone(), field_select) {
+                Ok(value) => match values.entry(key) {
+                    Entry::Vacant(vacant) => {
+                        vacant.insert(value);
+                    }
+                    Entry::Occupied(occupied) => errors.register_error(
+                        ErrorCode::DuplicatedKey(entry_path.clone(), occupied.key().clone()),
+                        None,
+                    ),
+                },
+                Err(error_code) => errors.register_error(error_code, None),
+            }
+        }
+    }
+    Ok(values)
+}
+
+pub fn format_values(
+    values: &BTreeMap<String, String>,
+    format: ExportFormat,
+) -> Result<String, Box<dyn Error>> {
+    let content = match format {
+        ExportFormat::Dotenv => values
+            .iter()
+            .map(|(key, value)| format!("{key}={}\n", quote_dotenv(value)))
+            .collect(),
+        ExportFormat::Json => serde_json::to_string_pretty(values)? + "\n",
+        ExportFormat::Yaml => serde_yaml::to_string(values)?,
+        ExportFormat::Toml => toml::to_string(values)?,
+    };
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::errors_and_warnings::HelperErrors;
+    use crate::app::generators::parse_dotenv;
+    use crate::app::test_helpers::tests::get_test_db;
+
+    use super::*;
+
+    #[test]
+    fn test_upper_snake_names() {
+        assert_eq!(to_upper_snake("Some weird name"), "SOME_WEIRD_NAME");
+        assert_eq!(to_upper_snake("apiKey"), "API_KEY");
+        assert_eq!(to_upper_snake("attribute-1"), "ATTRIBUTE_1");
+        assert_eq!(to_upper_snake("  Stripe / live  "), "STRIPE_LIVE");
+    }
+
+    #[test]
+    fn test_collect_group_passwords() {
+        let errors = HelperErrors::new();
+        let values =
+            collect_group(&get_test_db(), "group1", &ExportOptions::default(), &errors).unwrap();
+
+        let keys: Vec<&String> = values.keys().collect();
+        assert_eq!(keys, vec!["SOME_WEIRD_NAME", "TEST2", "TEST3"]);
+        assert_eq!(values.get("TEST2"), Some(&String::from("testing")));
+        assert!(errors.get_errors().is_empty());
+    }
+
+    #[test]
+    fn test_collect_group_multiple_fields_with_prefix() {
+        let errors = HelperErrors::new();
+        let options = ExportOptions {
+            prefix: String::from("APP_"),
+            fields: vec![String::from("username"), String::from("url")],
+            ..ExportOptions::default()
+        };
+        let values = collect_group(&get_test_db(), "/", &options, &errors).unwrap();
+
+        assert_eq!(
+            values.get("APP_COMPLEX_USERNAME"),
+            Some(&String::from("complex-user"))
+        );
+        assert_eq!(
+            values.get("APP_COMPLEX_URL"),
+            Some(&String::from("http://complex.url"))
+        );
+        assert_eq!(
+            values.get("APP_TEST1_USERNAME"),
+            Some(&String::from("user1"))
+        );
+        assert!(!values.contains_key("APP_TEST1_URL"));
+        // test1 has no url, missing has neither username nor url
+        assert_eq!(errors.get_errors().len(), 3);
+    }
+
+    #[test]
+    fn test_collect_group_attribute_naming() {
+        let errors = HelperErrors::new();
+        let options = ExportOptions {
+            naming: KeyNaming::Title,
+            key_attribute: Some(String::from("attribute-1")),
+            ..ExportOptions::default()
+        };
+        let values = collect_group(&get_test_db(), "", &options, &errors).unwrap();
+
+        assert_eq!(
+            values.get("protected-attribute"),
+            Some(&String::from("complex-password"))
+        );
+        assert!(values.contains_key("test1"));
+    }
+
+    #[test]
+    fn test_format_dotenv_multiline() {
+        let values = BTreeMap::from([(
+            String::from("KEY"),
+            String::from("-----BEGIN KEY-----\nab\\c\"$d\n-----END KEY-----"),
+        )]);
+
+        let content = format_values(&values, ExportFormat::Dotenv).unwrap();
+
+        assert_eq!(
+            content,
+            "KEY=\"-----BEGIN KEY-----\\nab\\\\c\\\"\\$d\\n-----END KEY-----\"\n"
+        );
+        assert_eq!(parse_dotenv(&content), values);
+    }
+
+    #[test]
+    fn test_collect_missing_group() {
+        let errors = HelperErrors::new();
+        let result = collect_group(
+            &get_test_db(),
+            "not/a/group",
+            &ExportOptions::default(),
+            &errors,
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Group 'not/a/group' not found in the KeePass database"
+        );
+    }
+
+    #[test]
+    fn test_format_values() {
+        let values = BTreeMap::from([
+            (String::from("B_KEY"), String::from("with \"quotes\" and $")),
+            (String::from("A_KEY"), String::from("plain")),
+        ]);
+
+        assert_eq!(
+            format_values(&values, ExportFormat::Dotenv).unwrap(),
+            "A_KEY=\"plain\"\nB_KEY=\"with \\\"quotes\\\" and \\$\"\n"
+        );
+        assert_eq!(
+            format_values(&values, ExportFormat::Json).unwrap(),
+            "{\n  \"A_KEY\": \"plain\",\n  \"B_KEY\": \"with \\\"quotes\\\" and $\"\n}\n"
+        );
+        assert_eq!(
+            format_values(&values, ExportFormat::Yaml).unwrap(),
+            "A_KEY: plain\nB_KEY: with \"quotes\" and $\n"
+        );
+        assert_eq!(
+            format_values(&values, ExportFormat::Toml).unwrap(),
+            "A_KEY = \"plain\"\nB_KEY = 'with \"quotes\" and $'\n"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use std::{error::Error, path::Path};
+
+use serde::{Deserialize, Deserializer};
+
+use super::config::yaml::RequiredVariables;
+use super::escaping::EscapeStrategy;
+
+const DELIMITER: &str = "---";
+
+/// Settings declared at the top of a template, between two `---` lines
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct FrontMatter {
+    /// Output path, relative to the folder of the template
+    pub output: Option<String>,
+    /// Alias of the database in the `databases` of the configuration
+    pub database: Option<String>,
+    #[serde(default)]
+    pub required_variables: RequiredVariables,
+    #[serde(default, deserialize_with = "deserialize_mode")]
+    pub mode: Option<u32>,
+    pub escape: Option<EscapeStrategy>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMode {
+    Number(u64),
+    Text(String),
+}
+
+/// The mode is read as octal digits, like "0600" or 0600 that yaml keeps as a
+/// string. Numbers are rejected, 640 or 0o600 (384) lose the written digits
+fn deserialize_mode<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let digits = match Option::<RawMode>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(RawMode::Number(number)) => {
+            return Err(serde::de::Error::custom(format!(
+                "Invalid file mode {number}, write it in quotes as an octal number like \"0600\""
+            )));
+        }
+        Some(RawMode::Text(text)) => text,
+    };
+    u32::from_str_radix(digits.trim_start_matches("0o"), 8)
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("Invalid file mode '{digits}'")))
+}
+
+/// Returns the front-matter and the rest of the template
+pub fn split_front_matter(content: &str) -> Result<(FrontMatter, &str), Box<dyn Error>> {
+    let Some(rest) = content.strip_prefix(DELIMITER).and_then(|rest| {
+        rest.strip_prefix('\n')
+            .or_else(|| rest.strip_prefix("\r\n"))
+    }) else {
+        return Ok((FrontMatter::default(), content));
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == DELIMITER {
+            let front_matter = serde_yaml::from_str::<Option<FrontMatter>>(&rest[..offset])
+                .map_err(|e| format!("Invalid front-matter: {e}"))?
+                .unwrap_or_default();
+            return Ok((front_matter, &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    Err("The front-matter is not closed with ---".into())
+}
+
+/// Lines before the body, to give the positions of the body as lines of the file
+pub fn front_matter_lines(content: &str, body: &str) -> usize {
+    content[..content.len() - body.len()].matches('\n').count()
+}
+
+pub fn read_front_matter(template: &str) -> Result<FrontMatter, Box<dyn Error>> {
+    let content = std::fs::read_to_string(template)
+        .map_err(|e| format!("Failed to read template {template}: {e}"))?;
+    let (front_matter, _) = split_front_matter(&content)?;
+    Ok(front_matter)
+}
+
+impl FrontMatter {
+    /// The output declared in the front-matter, relative to the template
+    pub fn output_path(&self, template: &str) -> Option<String> {
+        self.output.as_ref().map(|output| {
+            let folder = Path::new(template).parent().unwrap_or(Path::new(""));
+            folder.join(output).display().to_string()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_front_matter() {
+        let content = "---
+output: ../.env
+database: work
+mode: 0600
+required_variables:
+  email:
+    description: Your e-mail
+---
+EMAIL={{email}}
+";
+
+        let (front_matter, template) = split_front_matter(content).unwrap();
+
+        assert_eq!(template, "EMAIL={{email}}\n");
+        assert_eq!(front_matter.output, Some(String::from("../.env")));
+        assert_eq!(front_matter.database, Some(String::from("work")));
+        assert_eq!(front_matter.mode, Some(0o600));
+        assert!(front_matter.required_variables.contains_key("email"));
+        assert_eq!(
+            front_matter.output_path("project/templates/app.env.hbs"),
+            Some(String::from("project/templates/../.env"))
+        );
+    }
+
+    #[test]
+    fn test_without_front_matter() {
+        let content = "A={{a}}\n---\n";
+
+        let (front_matter, template) = split_front_matter(content).unwrap();
+
+        assert_eq!(front_matter, FrontMatter::default());
+        assert_eq!(template, content);
+    }
+
+    #[test]
+    fn test_empty_front_matter_and_text_mode() {
+        let (front_matter, template) = split_front_matter("---\n---\nA=1").unwrap();
+        assert_eq!(front_matter, FrontMatter::default());
+        assert_eq!(template, "A=1");
+
+        let (front_matter, _) = split_front_matter("---\nmode: \"640\"\n---\n").unwrap();
+        assert_eq!(front_matter.mode, Some(0o640));
+    }
+
+    #[test]
+    fn test_mode_forms() {
+        let mode = |value: &str| {
+            split_front_matter(&format!("---\nmode: {value}\n---\n"))
+                .map(|(front_matter, _)| front_matter.mode)
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(mode("\"0600\""), Ok(Some(0o600)));
+        assert_eq!(mode("\"0o600\""), Ok(Some(0o600)));
+        assert_eq!(mode("0600"), Ok(Some(0o600)));
+        assert!(
+            mode("640")
+                .unwrap_err()
+                .starts_with("Invalid front-matter: Invalid file mode 640, write it in quotes")
+        );
+        assert!(
+            mode("0o600")
+                .unwrap_err()
+                .starts_with("Invalid front-matter: Invalid file mode 384, write it in quotes")
+        );
+    }
+
+    #[test]
+    fn test_invalid_front_matter() {
+        assert_eq!(
+            split_front_matter("---\noutput: .env\n")
+                .unwrap_err()
+                .to_string(),
+            "The front-matter is not closed with ---"
+        );
+        assert!(
+            split_front_matter("---\nmode: \"999\"\n---\n")
+                .unwrap_err()
+                .to_string()
+                .starts_with("Invalid front-matter: Invalid file mode '999'")
+        );
+    }
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputGenerator {
+    /// Kubernetes Secret manifest with the values base64 encoded
+    K8sSecret,
+    /// Directory with one file per key, as used by docker secrets
+    DockerSecrets,
+    /// File to be used as systemd EnvironmentFile
+    SystemdEnv,
+    /// Directory with one file per key and a drop-in with the LoadCredential lines
+    SystemdCredentials,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratorOptions {
+    pub generator: OutputGenerator,
+    pub name: Option<String>,
+    pub namespace: Option<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+pub const SYSTEMD_CREDENTIALS_DROP_IN: &str = "load-credentials.conf";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct K8sSecret {
+    api_version: String,
+    kind: String,
+    metadata: K8sMetadata,
+    #[serde(rename = "type")]
+    secret_type: String,
+    data: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct K8sMetadata {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    namespace: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    labels: BTreeMap<String, String>,
+}
+
+pub fn unquote_dotenv(value: &str) -> String {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        let mut result = String::new();
+        let mut chars = value[1..value.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some(escaped) => result.push(escaped),
+                    None => result.push(c),
+                }
+            } else {
+                result.push(c);
+            }
+        }
+        result
+    } else if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        value[1..value.len() - 1].to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Reads the KEY=value lines of a rendered dotenv, comments and empty lines are ignored
+pub fn parse_dotenv(content: &str) -> BTreeMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let key = key.trim();
+            if key.is_empty() {
+                return None;
+            }
+            Some((key.to_string(), unquote_dotenv(value)))
+        })
+        .collect()
+}
+
+fn default_secret_name(output_path: &str) -> String {
+    let file_name = Path::new(output_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let name = file_name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>();
+    let name = name.trim_matches('-');
+    if name.is_empty() {
+        String::from("secret")
+    } else {
+        name.to_string()
+    }
+}
+
+/// The keys of the data of a secret follow the kubernetes charset
+fn check_k8s_key(key: &str) -> Result<(), String> {
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid key '{key}' for a kubernetes secret, only letters, digits, '-', '_' and '.' are allowed"
+        ))
+    }
+}
+
+/// The keys are written as files of the directory, they cannot be paths
+fn check_file_key(key: &str) -> Result<(), String> {
+    let valid = !key.is_empty()
+        && key != "."
+        && key != ".."
+        && !key.contains(['/', '\\'])
+        && !Path::new(key).is_absolute();
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid key '{key}', it cannot be used as the name of a file"
+        ))
+    }
+}
+
+fn k8s_secret(
+    values: &BTreeMap<String, String>,
+    options: &GeneratorOptions,
+    output_path: &str,
+) -> Result<String, Box<dyn Error>> {
+    for key in values.keys() {
+        check_k8s_key(key)?;
+    }
+    let secret = K8sSecret {
+        api_version: String::from("v1"),
+        kind: String::from("Secret"),
+        metadata: K8sMetadata {
+            name: options
+                .name
+                .clone()
+                .unwrap_or_else(|| default_secret_name(output_path)),
+            namespace: options.namespace.clone(),
+            labels: options.labels.clone(),
+        },
+        secret_type: String::from("Opaque"),
+        data: values
+            .iter()
+            .map(|(key, value)| (key.clone(), STANDARD.encode(value)))
+            .collect(),
+    };
+    Ok(serde_yaml::to_string(&secret)?)
+}
+
+fn quote_systemd(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' | '"' | '`' | '$' => {
+                result.push('\\');
+                result.push(c);
+            }
+            '\n' => result.push_str("\\n"),
+            _ => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn systemd_env(values: &BTreeMap<String, String>) -> String {
+    values
+        .iter()
+        .map(|(key, value)| format!("{key}={}\n", quote_systemd(value)))
+        .collect()
+}
+
+/// The file is only readable by the owner before anything is written
+fn write_secret_file(path: &Path, value: &str) -> Result<(), Box<dyn Error>> {
+    let error = |e: std::io::Error| format!("Failed to write output file {}: {e}", path.display());
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(error)?;
+    // The mode is only used when the file is created
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .map_err(error)?;
+    }
+    file.write_all(value.as_bytes()).map_err(error)?;
+    Ok(())
+}
+
+fn write_secrets_directory(
+    values: &BTreeMap<String, String>,
+    output_path: &str,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    for key in values.keys() {
+        check_file_key(key)?;
+    }
+    let directory = Path::new(output_path);
+    std::fs::create_dir_all(directory)
+        .map_err(|e| format!("Failed to create output directory {output_path}: {e}"))?;
+    let mut written = Vec::new();
+    for (key, value) in values {
+        let file = directory.join(key);
+        write_secret_file(&file, value)?;
+        written.push(file);
+    }
+    Ok(written)
+}
+
+fn systemd_credentials_drop_in(files: &[PathBuf], values: &BTreeMap<String, String>) -> String {
+    let mut drop_in = String::from("[Service]\n");
+    for (file, key) in files.iter().zip(values.keys()) {
+        drop_in.push_str(&format!("LoadCredential={key}:{}\n", file.display()));
+    }
+    drop_in
+}
+
+/// Writes the values in the layout of the selected generator, returning the
+/// list of files written
+pub fn write_generated(
+    values: &BTreeMap<String, String>,
+    options: &GeneratorOptions,
+    output_path: &str,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let output = Path::new(output_path);
+    let written = match options.generator {
+        OutputGenerator::K8sSecret => {
+            write_secret_file(output, &k8s_secret(values, options, output_path)?)?;
+            vec![output.to_path_buf()]
+        }
+        OutputGenerator::SystemdEnv => {
+            write_secret_file(output, &systemd_env(values))?;
+            vec![output.to_path_buf()]
+        }
+        OutputGenerator::DockerSecrets => write_secrets_directory(values, output_path)?,
+        OutputGenerator::SystemdCredentials => {
+            let mut written = write_secrets_directory(values, output_path)?;
+            let drop_in_path = output.join(SYSTEMD_CREDENTIALS_DROP_IN);
+            let drop_in = systemd_credentials_drop_in(&written, values);
+            std::fs::write(&drop_in_path, drop_in).map_err(|e| {
+                format!(
+                    "Failed to write output file {}: {e}",
+                    drop_in_path.display()
+                )
+            })?;
+            written.push(drop_in_path);
+            written
+        }
+    };
+    Ok(written
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_helpers::TmpFile;
+
+    use super::*;
+
+    fn options(generator: OutputGenerator) -> GeneratorOptions {
+        GeneratorOptions {
+            generator,
+            name: None,
+            namespace: None,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_dotenv() {
+        let values = parse_dotenv(
+            "# comment\nexport PLAIN=value\nQUOTED=\"with \\\"quotes\\\" and \\$\"\n\nSINGLE='a b'\n=invalid\n",
+        );
+
+        assert_eq!(values.len(), 3);
+        assert_eq!(values.get("PLAIN"), Some(&String::from("value")));
+        assert_eq!(
+            values.get("QUOTED"),
+            Some(&String::from("with \"quotes\" and $"))
+        );
+        assert_eq!(values.get("SINGLE"), Some(&String::from("a b")));
+    }
+
+    #[test]
+    fn test_k8s_secret() {
+        let values = BTreeMap::from([(String::from("PASSWORD"), String::from("testing"))]);
+        let options = GeneratorOptions {
+            namespace: Some(String::from("staging")),
+            labels: BTreeMap::from([(String::from("app"), String::from("api"))]),
+            ..options(OutputGenerator::K8sSecret)
+        };
+
+        let secret = k8s_secret(&values, &options, "/some/path/api.env").unwrap();
+        assert_eq!(
+            secret,
+            "apiVersion: v1
+kind: Secret
+metadata:
+  name: api-env
+  namespace: staging
+  labels:
+    app: api
+type: Opaque
+data:
+  PASSWORD: dGVzdGluZw==
+"
+        );
+    }
+
+    #[test]
+    fn test_systemd_env() {
+        let values = BTreeMap::from([
+            (String::from("A"), String::from("some \"value\" with $HOME")),
+            (String::from("B"), String::from("back\\slash")),
+        ]);
+
+        assert_eq!(
+            systemd_env(&values),
+            "A=\"some \\\"value\\\" with \\$HOME\"\nB=\"back\\\\slash\"\n"
+        );
+    }
+
+    #[test]
+    fn test_systemd_credentials_directory() {
+        let directory = TmpFile::new_uuid("test_resources/tmp", None::<String>);
+        let values = BTreeMap::from([
+            (String::from("DB_PASSWORD"), String::from("testing")),
+            (String::from("API_KEY"), String::from("key")),
+        ]);
+
+        let written = write_generated(
+            &values,
+            &options(OutputGenerator::SystemdCredentials),
+            &directory.get(),
+        )
+        .unwrap();
+
+        assert_eq!(written.len(), 3);
+        let db_password =
+            std::fs::read_to_string(format!("{}/DB_PASSWORD", directory.get())).unwrap();
+        assert_eq!(db_password, "testing");
+        let drop_in = std::fs::read_to_string(format!(
+            "{}/{}",
+            directory.get(),
+            SYSTEMD_CREDENTIALS_DROP_IN
+        ))
+        .unwrap();
+        assert!(drop_in.starts_with("[Service]\nLoadCredential=API_KEY:"));
+        assert!(drop_in.contains("LoadCredential=DB_PASSWORD:"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = std::fs::metadata(format!("{}/DB_PASSWORD", directory.get())).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(directory.get()).unwrap();
+    }
+
+    #[test]
+    fn test_k8s_secret_invalid_key() {
+        let values = BTreeMap::from([(String::from("MY KEY"), String::from("testing"))]);
+
+        let result = k8s_secret(&values, &options(OutputGenerator::K8sSecret), "api.env");
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid key 'MY KEY' for a kubernetes secret, only letters, digits, '-', '_' and '.' are allowed"
+        );
+        let values = BTreeMap::from([(String::from("tls.crt-1_A"), String::from("x"))]);
+        assert!(k8s_secret(&values, &options(OutputGenerator::K8sSecret), "api.env").is_ok());
+    }
+
+    #[test]
+    fn test_secrets_directory_invalid_keys() {
+        let directory = TmpFile::new_uuid("test_resources/tmp", None::<String>);
+        let escaped = format!("{}-escaped", directory.get());
+        let escaped_name = Path::new(&escaped).file_name().unwrap().to_str().unwrap();
+        for key in [
+            format!("../{escaped_name}"),
+            String::from("a/b"),
+            String::from(".."),
+            escaped.clone(),
+        ] {
+            let values = BTreeMap::from([(key.clone(), String::from("testing"))]);
+
+            let result = write_generated(
+                &values,
+                &options(OutputGenerator::DockerSecrets),
+                &directory.get(),
+            );
+
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                format!("Invalid key '{key}', it cannot be used as the name of a file")
+            );
+        }
+        assert!(!Path::new(&escaped).exists());
+    }
+}
@@ -1,16 +1,27 @@
+use std::{cell::OnceCell, path::Path};
+
 use handlebars::{
-    Context, Handlebars, Helper, HelperDef, JsonRender, JsonValue, PathAndJson, RenderContext,
-    RenderError, ScopedJson, handlebars_helper, no_escape,
+    Context, Handlebars, Helper, HelperDef, HelperResult, JsonRender, JsonValue, Output,
+    PathAndJson, RenderContext, RenderError, RenderErrorReason, ScopedJson, Template, no_escape,
 };
-use keepass::Database;
-
-use super::errors_and_warnings::{ErrorCode, ErrorRecord};
+use keepass::db::{Entry, fields};
+
+use super::database::{DEFAULT_PASSWORD_LENGTH, SharedDatabase, generate_password};
+use super::encoding::register_encoding_helpers;
+use super::errors_and_warnings::{ErrorCode, ErrorLocation, ErrorRecord};
+use super::escaping::{dotenv_escape, json_escape, shell_quote, toml_escape, yaml_escape};
+use super::export::split_group_path;
+use super::inspect::helper_positions;
+use super::keepass_url::KeepassUrlHelper;
+use super::lock::ReadInputs;
 use super::tools::convert_vecs;
 
 pub type LibHandlebars<'reg> = Handlebars<'reg>;
 
+#[derive(Clone)]
 pub struct KeepassHelper<'a> {
-    db: Database,
+    db: SharedDatabase,
+    group: Vec<String>,
     errors: &'a dyn ErrorRecord,
 }
 
@@ -21,14 +32,35 @@ const NO_URL_ERROR: &str = "<No URL found in entry>";
 const NO_ENTRY_CONFIGURED: &str = "<No entry was configured>";
 const ATTRIBUTE_NOT_FOUND_ERROR: &str = "<Attribute ({field-name}) not found in entry>";
 
-#[derive(Debug)]
-enum FieldSelect {
+#[derive(Debug, Clone)]
+pub enum FieldSelect {
     Password,
     Username,
     Url,
     AdditionalAttributes { field_name: String },
 }
 
+impl FieldSelect {
+    pub fn from_name(field: String) -> Self {
+        match field.to_lowercase().as_str() {
+            "username" => FieldSelect::Username,
+            "password" => FieldSelect::Password,
+            "url" => FieldSelect::Url,
+            _ => FieldSelect::AdditionalAttributes { field_name: field },
+        }
+    }
+
+    /// Name of the field in the KeePass entry
+    pub fn key(&self) -> &str {
+        match self {
+            FieldSelect::Password => fields::PASSWORD,
+            FieldSelect::Username => fields::USERNAME,
+            FieldSelect::Url => fields::URL,
+            FieldSelect::AdditionalAttributes { field_name } => field_name,
+        }
+    }
+}
+
 fn extract_field_value(field_path: Option<&PathAndJson>) -> Option<String> {
     if let Some(field_path) = field_path {
         if let Some(field_value) = field_path.relative_path() {
@@ -45,12 +77,7 @@ fn extract_field_value(field_path: Option<&PathAndJson>) -> Option<String> {
 
 fn extract_field_type(field_path: Option<&PathAndJson>) -> FieldSelect {
     if let Some(field) = extract_field_value(field_path) {
-        return match field.to_lowercase().as_str() {
-            "username" => FieldSelect::Username,
-            "password" => FieldSelect::Password,
-            "url" => FieldSelect::Url,
-            _ => FieldSelect::AdditionalAttributes { field_name: field },
-        };
+        return FieldSelect::from_name(field);
     }
     FieldSelect::Password
 }
@@ -60,6 +87,7 @@ impl ErrorCode {
         match self {
             ErrorCode::GroupFound(_) => NOT_FOUND_ERROR.into(),
             ErrorCode::MissingEntry(_) => NOT_FOUND_ERROR.into(),
+            ErrorCode::WriteNotAllowed(_) => NOT_FOUND_ERROR.into(),
             ErrorCode::MissingField(_, field_name) => {
                 ATTRIBUTE_NOT_FOUND_ERROR.replace("{field-name}", field_name.as_str())
             }
@@ -68,19 +96,50 @@ impl ErrorCode {
             ErrorCode::NoUrl(_) => NO_URL_ERROR.into(),
             ErrorCode::MissingPath => NO_ENTRY_CONFIGURED.into(),
             ErrorCode::DeprecatedPathFormat(_) => "Unused".into(),
+            ErrorCode::DuplicatedKey(_, _) => "Unused".into(),
+        }
+    }
+}
+
+pub fn extract_entry_field(
+    entry: &Entry,
+    path_str: Vec<String>,
+    field: FieldSelect,
+) -> Result<String, ErrorCode> {
+    match field {
+        FieldSelect::Password => match entry.get_password() {
+            Some(pwd) => Ok(pwd.into()),
+            None => Err(ErrorCode::NoPassword(path_str)),
+        },
+        FieldSelect::Username => match entry.get_username() {
+            Some(username) => Ok(username.into()),
+            None => Err(ErrorCode::NoUsername(path_str)),
+        },
+        FieldSelect::Url => match entry.get_url() {
+            Some(url) => Ok(url.into()),
+            None => Err(ErrorCode::NoUrl(path_str)),
+        },
+        FieldSelect::AdditionalAttributes { field_name } => {
+            let result = entry.get(field_name.as_str());
+            match result {
+                Some(d2) => Ok(d2.into()),
+                None => Err(ErrorCode::MissingField(path_str, field_name.clone())),
+            }
         }
     }
 }
 
 impl KeepassHelper<'_> {
-    fn extract_entry(
+    /// Looks for the entry and passes it to the callback
+    pub fn with_entry<T>(
         &self,
         path_str: Vec<String>,
-        field: FieldSelect,
-    ) -> Result<String, ErrorCode> {
+        callback: impl FnOnce(&Entry, Vec<String>) -> Result<T, ErrorCode>,
+    ) -> Result<T, ErrorCode> {
         let mut path: Vec<&str> = path_str.iter().map(|x| x.as_str()).collect::<Vec<&str>>();
         let entry = path.pop().unwrap_or("invalid-path");
-        if let Some(group) = self.db.root().group_by_path(&path) {
+        let db = self.db.read();
+        if let Some(group) = db.root().group_by_path(&path) {
             let Some(entry) = group.entry_by_name(entry) else {
                 return match group.group_by_name(entry) {
                     Some(_) => Err(ErrorCode::GroupFound(path_str)),
@@ -88,89 +147,369 @@ impl KeepassHelper<'_> {
                 };
             };
 
-            match field {
-                FieldSelect::Password => match entry.get_password() {
-                    Some(pwd) => Ok(pwd.into()),
-                    None => Err(ErrorCode::NoPassword(path_str)),
-                },
-                FieldSelect::Username => match entry.get_username() {
-                    Some(username) => Ok(username.into()),
-                    None => Err(ErrorCode::NoUsername(path_str)),
-                },
-                FieldSelect::Url => match entry.get_url() {
-                    Some(url) => Ok(url.into()),
-                    None => Err(ErrorCode::NoUrl(path_str)),
-                },
-                FieldSelect::AdditionalAttributes { field_name } => {
-                    let result = entry.get(field_name.as_str());
-                    match result {
-                        Some(d2) => Ok(d2.into()),
-                        None => Err(ErrorCode::MissingField(path_str, field_name.clone())),
-                    }
-                }
-            }
+            self.db.record_access(&path_str, &entry);
+            callback(&entry, path_str)
         } else {
             Err(ErrorCode::MissingEntry(path_str))
         }
     }
-}
 
-impl HelperDef for KeepassHelper<'_> {
-    fn call_inner<'reg: 'rc, 'rc>(
+    fn extract_entry(
         &self,
-        h: &Helper<'rc>,
-        _: &'reg Handlebars<'reg>,
-        _: &'rc Context,
-        _: &mut RenderContext<'reg, 'rc>,
-    ) -> std::result::Result<ScopedJson<'rc>, RenderError> {
-        let args = h
+        path_str: Vec<String>,
+        field: FieldSelect,
+    ) -> Result<String, ErrorCode> {
+        self.with_entry(path_str, |entry, path_str| {
+            extract_entry_field(entry, path_str, field)
+        })
+    }
+
+    /// Starts a call of the helper, counting it to know which of the
+    /// identical calls of the template is rendered
+    pub fn helper_call<'a, 'rc>(
+        &self,
+        h: &'a Helper<'rc>,
+        r: &'a Handlebars,
+        rc: &'a RenderContext,
+    ) -> HelperCall<'a, 'rc> {
+        // The blocks, like each, are rendered without name, for them the root
+        // template is used
+        let template = rc
+            .get_current_template_name()
+            .or(rc.get_root_template_name())
+            .and_then(|name| Some((name, r.get_template(name)?)));
+        let occurrence = template.map_or(0, |(name, _)| {
+            self.errors.count_call(format!("{name} {}", call_key(h)))
+        });
+        HelperCall {
+            h,
+            template,
+            occurrence,
+            location: OnceCell::new(),
+        }
+    }
+
+    /// Path of the entry from the parameters of the helper, inside the group
+    pub fn entry_path(&self, call: &HelperCall) -> Result<Vec<String>, ErrorCode> {
+        let args = call
+            .h
             .params()
             .iter()
             .map(|x| x.render())
             .collect::<Vec<String>>();
         if args.is_empty() {
-            self.errors.register_error(ErrorCode::MissingPath);
-            return Ok(ScopedJson::Derived(JsonValue::from(
-                ErrorCode::MissingPath.to_hb_entry(),
-            )));
+            return Err(ErrorCode::MissingPath);
         }
         let path = if args.len() == 1 {
             convert_vecs(args[0].split("/").collect())
         } else {
-            self.errors
-                .register_error(ErrorCode::DeprecatedPathFormat(args.clone()));
+            self.errors.register_error(
+                ErrorCode::DeprecatedPathFormat(args.clone()),
+                call.location(),
+            );
             args
         };
+        Ok([self.group.clone(), path].concat())
+    }
+
+    /// Registers the error and returns the text written in its place
+    pub fn error_output<'rc>(&self, error_code: ErrorCode, call: &HelperCall) -> ScopedJson<'rc> {
+        let output = error_code.to_hb_entry();
+        self.errors.register_error(error_code, call.location());
+        ScopedJson::Derived(JsonValue::from(output))
+    }
+}
+
+/// Identifies the calls with the same parameters, the ones with a variable
+/// are the same call whatever its value
+fn call_key(h: &Helper) -> String {
+    let value = |value: &PathAndJson| {
+        value
+            .relative_path()
+            .cloned()
+            .unwrap_or_else(|| value.value().to_string())
+    };
+    let params: Vec<String> = h.params().iter().map(value).collect();
+    let hash: Vec<String> = h
+        .hash()
+        .iter()
+        .map(|(key, parameter)| format!("{key}={}", value(parameter)))
+        .collect();
+    format!("{} {} {}", h.name(), params.join(" "), hash.join(" "))
+}
+
+/// A call of a helper being rendered, its location is looked up on its first
+/// error
+pub struct HelperCall<'a, 'rc> {
+    h: &'a Helper<'rc>,
+    template: Option<(&'a String, &'a Template)>,
+    /// Index of the call among the identical ones of the template
+    occurrence: usize,
+    location: OnceCell<Option<ErrorLocation>>,
+}
+
+impl HelperCall<'_, '_> {
+    /// Where the helper is called, the identical calls are rendered in the
+    /// order they are written, repeating them inside the blocks like each
+    fn location(&self) -> Option<ErrorLocation> {
+        self.location
+            .get_or_init(|| {
+                let (name, template) = self.template?;
+                let positions = helper_positions(template, self.h);
+                let (line, column) = *positions.get(self.occurrence % positions.len().max(1))?;
+                Some(ErrorLocation {
+                    template: name.clone(),
+                    line,
+                    column,
+                })
+            })
+            .clone()
+    }
+}
+
+struct GenerateOptions {
+    length: usize,
+    charset: String,
+}
+
+/// Options of generate=true, the charset is checked before looking for the entry
+fn generate_options(h: &Helper) -> Result<Option<GenerateOptions>, RenderError> {
+    let generate = h
+        .hash_get("generate")
+        .is_some_and(|generate| generate.value().as_bool() == Some(true));
+    if !generate {
+        return Ok(None);
+    }
+    let length = match h.hash_get("length") {
+        Some(length) => length.value().as_u64().ok_or_else(|| {
+            RenderError::from(RenderErrorReason::Other(String::from(
+                "The length of the generated password must be a number",
+            )))
+        })? as usize,
+        None => DEFAULT_PASSWORD_LENGTH,
+    };
+    let charset = h
+        .hash_get("charset")
+        .map(|charset| charset.value().render())
+        .unwrap_or_else(|| String::from("alnum"));
+    generate_password(0, &charset).map_err(RenderErrorReason::Other)?;
+    Ok(Some(GenerateOptions { length, charset }))
+}
+
+impl KeepassHelper<'_> {
+    /// Creates the missing entry with a random password when the database
+    /// can be written
+    fn generate_entry(
+        &self,
+        path: Vec<String>,
+        field: FieldSelect,
+        options: &GenerateOptions,
+    ) -> Result<String, ErrorCode> {
+        if !self.db.is_writable() {
+            return Err(ErrorCode::WriteNotAllowed(path));
+        }
+        let password = generate_password(options.length, &options.charset)
+            .expect("The charset is validated before");
+        self.db.add_entry(&path, &password);
+        self.extract_entry(path, field)
+    }
+}
+
+impl HelperDef for KeepassHelper<'_> {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> std::result::Result<ScopedJson<'rc>, RenderError> {
         let field = extract_field_type(h.hash_get("field"));
-        match self.extract_entry(path, field) {
-            Ok(content) => Ok(ScopedJson::from(JsonValue::from(content))),
-            Err(error_code) => {
-                self.errors.register_error(error_code.clone());
-                Ok(ScopedJson::Derived(JsonValue::from(
-                    error_code.to_hb_entry(),
-                )))
+        let generate = generate_options(h)?;
+        let call = self.helper_call(h, r, rc);
+        let content = self.entry_path(&call).and_then(|path| {
+            match (self.extract_entry(path.clone(), field.clone()), &generate) {
+                (Err(ErrorCode::MissingEntry(_)), Some(options)) => {
+                    self.generate_entry(path, field, options)
+                }
+                (content, _) => content,
             }
+        });
+        match content {
+            Ok(content) => Ok(ScopedJson::from(JsonValue::from(content))),
+            Err(error_code) => Ok(self.error_output(error_code, &call)),
         }
     }
 }
 
-handlebars_helper!(stringify: |x: String| {
-    format!("\"{}\"", x.replace('\"', "\\\"").replace('$', "\\$"))
-});
+pub fn quote_dotenv(value: &str) -> String {
+    format!("\"{}\"", dotenv_escape(value))
+}
+
+/// Helpers that format their parameter, the output is written as it is
+/// without applying the escaping of the template again
+#[derive(Clone, Copy)]
+struct FormatHelper {
+    name: &'static str,
+    format: fn(&str) -> String,
+}
 
-pub fn build_handlebars(db: Database, errors: &dyn ErrorRecord) -> Handlebars<'_> {
+impl HelperDef for FormatHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> std::result::Result<ScopedJson<'rc>, RenderError> {
+        let value = required_param(h, self.name)?;
+        Ok(ScopedJson::Derived(JsonValue::from((self.format)(&value))))
+    }
+
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let value = required_param(h, self.name)?;
+        out.write(&(self.format)(&value))?;
+        Ok(())
+    }
+}
+
+const FORMAT_HELPERS: [FormatHelper; 5] = [
+    FormatHelper {
+        name: "stringify",
+        format: quote_dotenv,
+    },
+    FormatHelper {
+        name: "json_escape",
+        format: json_escape,
+    },
+    FormatHelper {
+        name: "yaml_escape",
+        format: yaml_escape,
+    },
+    FormatHelper {
+        name: "toml_escape",
+        format: toml_escape,
+    },
+    FormatHelper {
+        name: "shell_quote",
+        format: shell_quote,
+    },
+];
+
+/// The paths used in the keepass helper will be looked up inside the group
+pub fn build_handlebars(
+    db: impl Into<SharedDatabase>,
+    group: Option<String>,
+    errors: &dyn ErrorRecord,
+) -> Handlebars<'_> {
     let mut handlebars = Handlebars::new();
+    let group = group
+        .map(|group| split_group_path(&group))
+        .unwrap_or_default();
 
     handlebars.register_escape_fn(no_escape);
-    handlebars.register_helper("keepass", Box::new(KeepassHelper { db, errors }));
-    handlebars.register_helper("stringify", Box::new(stringify));
+    let keepass = KeepassHelper {
+        db: db.into(),
+        group,
+        errors,
+    };
+    handlebars.register_helper("keepass_url", Box::new(KeepassUrlHelper(keepass.clone())));
+    handlebars.register_helper("keepass", Box::new(keepass));
+    for helper in FORMAT_HELPERS {
+        handlebars.register_helper(helper.name, Box::new(helper));
+    }
+    register_encoding_helpers(&mut handlebars);
+    handlebars.register_helper("env", Box::new(EnvHelper(None)));
+    handlebars.register_helper("file", Box::new(FileHelper(None)));
 
     handlebars
 }
 
+/// Registers again the env and file helpers to record what they read
+pub fn record_reads(handlebars: &mut LibHandlebars, reads: &ReadInputs) {
+    handlebars.register_helper("env", Box::new(EnvHelper(Some(reads.clone()))));
+    handlebars.register_helper("file", Box::new(FileHelper(Some(reads.clone()))));
+}
+
+fn required_param(h: &Helper, helper: &str) -> Result<String, RenderError> {
+    h.param(0)
+        .map(|param| param.value().render())
+        .ok_or_else(|| {
+            RenderErrorReason::Other(format!("The {helper} helper requires a parameter")).into()
+        })
+}
+
+/// Writes an environment variable escaped like the keepass values, the reads
+/// are recorded when there is a record
+struct EnvHelper(Option<ReadInputs>);
+
+impl HelperDef for EnvHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> std::result::Result<ScopedJson<'rc>, RenderError> {
+        let name = required_param(h, "env")?;
+        let variable = std::env::var(&name).ok();
+        if let Some(reads) = &self.0 {
+            reads.record_env(&name, variable.as_deref());
+        }
+        let value = match (variable, h.hash_get("default")) {
+            (Some(value), _) => value,
+            (None, Some(default)) => default.value().render(),
+            (None, None) => {
+                return Err(RenderErrorReason::Other(format!(
+                    "Environment variable '{name}' is not set"
+                ))
+                .into());
+            }
+        };
+        Ok(ScopedJson::Derived(JsonValue::from(value)))
+    }
+}
+
+/// Inlines the contents of a file escaped like the keepass values, the
+/// relative paths are relative to the folder of the template being rendered
+struct FileHelper(Option<ReadInputs>);
+
+impl HelperDef for FileHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> std::result::Result<ScopedJson<'rc>, RenderError> {
+        let file = required_param(h, "file")?;
+        let template_dir = rc
+            .get_root_template_name()
+            .and_then(|template| Path::new(template).parent());
+        let path = match template_dir {
+            Some(dir) => dir.join(&file),
+            None => Path::new(&file).to_path_buf(),
+        };
+        let contents = std::fs::read_to_string(&path).map_err(|e| {
+            RenderErrorReason::Other(format!("Cannot read file {}: {e}", path.display()))
+        })?;
+        if let Some(reads) = &self.0 {
+            reads.record_file(&path, &contents);
+        }
+        Ok(ScopedJson::Derived(JsonValue::from(contents)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::app::errors_and_warnings::HelperErrors;
+    use crate::app::escaping::EscapeStrategy;
 
     use super::*;
 
@@ -181,13 +520,14 @@ mod tests {
         Database::open(&mut file, key).expect("Cannot open the DB")
     }
 
-    use keepass::DatabaseKey;
+    use keepass::{Database, DatabaseKey};
     use std::fs::File;
+    use test_helpers::TmpFile;
 
     #[test]
     fn test_handlebars_keepass_variables() {
         let errors_and_warnings = HelperErrors::new();
-        let handlebars = build_handlebars(get_db(), &errors_and_warnings);
+        let handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
 
         let template =
             "VAR_NAME=\"My name\"\nVAR_SECRET=\"{{keepass \"group1\" \"Some weird name\"}}\"";
@@ -202,7 +542,7 @@ mod tests {
     #[test]
     fn test_handlebars_keepass_with_string_that_needs_encoding() {
         let errors_and_warnings = HelperErrors::new();
-        let handlebars = build_handlebars(get_db(), &errors_and_warnings);
+        let handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
 
         let template = "VAR_NAME=\"My name\"\nVAR_SECRET={{stringify (keepass \"test1\")}}\"";
 
@@ -218,7 +558,7 @@ mod tests {
     fn test_handlebars_keepass_unknown_variable() {
         let errors_and_warnings = HelperErrors::new();
         {
-            let handlebars = build_handlebars(get_db(), &errors_and_warnings);
+            let handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
 
             let template = "VAR_SECRET=\"{{keepass \"not-found-group\"}}\"";
 
@@ -236,7 +576,7 @@ mod tests {
     #[test]
     fn test_handlebars_keepass_retrieve_username() {
         let errors_and_warnings = HelperErrors::new();
-        let handlebars = build_handlebars(get_db(), &errors_and_warnings);
+        let handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
 
         let template = "VAR_SECRET=\"{{keepass field=username \"test1\"}}\"";
 
@@ -251,7 +591,7 @@ mod tests {
     #[test]
     fn test_handlebars_keepass_retrieve_other_fields() {
         let errors_and_warnings = HelperErrors::new();
-        let handlebars = build_handlebars(get_db(), &errors_and_warnings);
+        let handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
 
         let template = "URL=\"{{keepass field=url \"complex\"}}\"
             ADDITIONAL=\"{{keepass field=attribute-1 \"complex\"}}\"
@@ -272,7 +612,7 @@ mod tests {
     fn test_handlebars_keepass_missing_fields() {
         let errors_and_warnings = HelperErrors::new();
         {
-            let handlebars = build_handlebars(get_db(), &errors_and_warnings);
+            let handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
 
             let template = "PASSWORD=\"{{keepass \"missing\"}}\"
                 USERNAME=\"{{keepass field=username \"missing\"}}\"
@@ -293,4 +633,182 @@ mod tests {
         println!("{:?}", errors);
         assert_eq!(errors.len(), 4)
     }
+
+    #[test]
+    fn test_handlebars_keepass_in_group() {
+        let errors_and_warnings = HelperErrors::new();
+        let handlebars =
+            build_handlebars(get_db(), Some(String::from("group1")), &errors_and_warnings);
+
+        let template = "PASSWORD={{keepass \"test2\"}}\nMISSING={{keepass \"test1\"}}";
+
+        let rendered = handlebars.render_template(template, &()).unwrap();
+        assert_eq!(
+            rendered,
+            "PASSWORD=testing\nMISSING=<Not found keepass entry>"
+        );
+        let errors = errors_and_warnings.get_errors();
+        assert!(
+            matches!(&errors[..], [error] if matches!(&error.code, ErrorCode::MissingEntry(path) if path == &["group1", "test1"]))
+        );
+    }
+
+    #[test]
+    fn test_handlebars_error_location() {
+        let errors_and_warnings = HelperErrors::new();
+        {
+            let mut handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
+            let template = "A={{keepass \"test1\"}}\n\
+                B={{stringify (keepass \"unknown\")}}\n\
+                {{#each names}}C={{keepass this field=\"username\"}}{{/each}}\n\
+                D={{keepass \"unknown\"}}\n\
+                E={{keepass \"unknown\"}}";
+            handlebars
+                .register_template_string("app.env.hbs", template)
+                .unwrap();
+
+            handlebars
+                .render("app.env.hbs", &serde_json::json!({"names": ["missing"]}))
+                .unwrap();
+        }
+
+        let locations: Vec<Option<ErrorLocation>> = errors_and_warnings
+            .get_errors()
+            .into_iter()
+            .map(|error| error.location)
+            .collect();
+        let location = |line, column| {
+            Some(ErrorLocation {
+                template: String::from("app.env.hbs"),
+                line,
+                column,
+            })
+        };
+        assert_eq!(
+            locations,
+            vec![
+                location(2, 3),
+                location(3, 18),
+                location(4, 3),
+                location(5, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_handlebars_keepass_generate() {
+        let errors_and_warnings = HelperErrors::new();
+        let template = "{{keepass \"Projects/new\" generate=true length=16 charset=\"hex\"}}\n\
+            {{keepass \"Projects/new\"}}\n{{keepass \"test1\" generate=true}}";
+        {
+            let database = SharedDatabase::new(get_db(), true);
+            let handlebars = build_handlebars(database.clone(), None, &errors_and_warnings);
+
+            let rendered = handlebars.render_template(template, &()).unwrap();
+            let lines: Vec<&str> = rendered.lines().collect();
+            assert_eq!(lines[0].len(), 16);
+            assert!(lines[0].chars().all(|c| c.is_ascii_hexdigit()));
+            assert_eq!(lines[1], lines[0]);
+            assert_eq!(lines[2], "8/k,9P`Y\"\"7)*]CNdM~,");
+            assert!(database.is_modified());
+
+            let result = handlebars
+                .render_template("{{keepass \"a\" generate=true charset=\"emoji\"}}", &());
+            assert!(
+                result
+                    .unwrap_err()
+                    .to_string()
+                    .contains("Unknown charset 'emoji'")
+            );
+        }
+        assert!(errors_and_warnings.get_errors().is_empty());
+
+        let handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
+        let rendered = handlebars
+            .render_template("{{keepass \"Projects/new\" generate=true}}", &())
+            .unwrap();
+        assert_eq!(rendered, "<Not found keepass entry>");
+        let errors = errors_and_warnings.get_errors();
+        assert!(
+            matches!(&errors[..], [error] if matches!(&error.code, ErrorCode::WriteNotAllowed(path) if path == &["Projects", "new"]))
+        );
+    }
+
+    #[test]
+    fn test_handlebars_env() {
+        let errors_and_warnings = HelperErrors::new();
+        let handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
+
+        let template =
+            "{{env \"CARGO_PKG_NAME\"}} {{env \"KEEPASS_2_FILE_MISSING\" default=\"x\"}}";
+        let rendered = handlebars.render_template(template, &()).unwrap();
+        assert_eq!(rendered, "keepass-2-file x");
+
+        let result = handlebars.render_template("{{env \"KEEPASS_2_FILE_MISSING\"}}", &());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Environment variable 'KEEPASS_2_FILE_MISSING' is not set")
+        );
+    }
+
+    #[test]
+    fn test_handlebars_env_escaped() {
+        // SAFETY: the variable is only used by this test
+        unsafe { std::env::set_var("KEEPASS_2_FILE_ESCAPED_ENV", "say \"hi\"\nbye") };
+        let errors_and_warnings = HelperErrors::new();
+        let mut handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
+        handlebars.register_escape_fn(|value| EscapeStrategy::Json.escape(value));
+
+        let template = "{\"a\": \"{{env \"KEEPASS_2_FILE_ESCAPED_ENV\"}}\"}";
+        let rendered = handlebars.render_template(template, &()).unwrap();
+
+        assert_eq!(rendered, "{\"a\": \"say \\\"hi\\\"\\nbye\"}");
+    }
+
+    #[test]
+    fn test_handlebars_file_relative_to_template() {
+        let errors_and_warnings = HelperErrors::new();
+        let mut handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
+        let certificate = TmpFile::new_uuid("test_resources/tmp", Some("pem"));
+        certificate.write(String::from("-----BEGIN CERTIFICATE-----"));
+        let file_name = Path::new(&certificate.get())
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let template = TmpFile::new_uuid("test_resources/tmp", Some("hbs"));
+        template.write(format!("CERT={{{{file \"{file_name}\"}}}}"));
+
+        handlebars
+            .register_template_file(&template.get(), template.get())
+            .unwrap();
+        let rendered = handlebars.render(&template.get(), &()).unwrap();
+        assert_eq!(rendered, "CERT=-----BEGIN CERTIFICATE-----");
+
+        let result = handlebars.render_template("{{file \"missing-file\"}}", &());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Cannot read file missing-file")
+        );
+    }
+
+    #[test]
+    fn test_handlebars_escape_helpers() {
+        let errors_and_warnings = HelperErrors::new();
+        let mut handlebars = build_handlebars(get_db(), None, &errors_and_warnings);
+        handlebars.register_escape_fn(|value| EscapeStrategy::Json.escape(value));
+
+        let template = "{\"a\": \"{{keepass \"test1\"}}\", \"b\": \"{{json_escape (keepass \"test1\")}}\"}\n\
+            {{shell_quote \"it's\"}} {{toml_escape \"a\\\\b\"}} {{stringify \"$x\"}}";
+        let rendered = handlebars.render_template(template, &()).unwrap();
+
+        assert_eq!(
+            rendered,
+            "{\"a\": \"8/k,9P`Y\\\"\\\"7)*]CNdM~,\", \"b\": \"8/k,9P`Y\\\"\\\"7)*]CNdM~,\"}\n'it'\\''s' a\\\\b \"\\$x\""
+        );
+    }
 }
@@ -0,0 +1,122 @@
+use std::{error::Error, path::Path, process::Command};
+
+use super::IOLogs;
+
+pub const OUTPUT_ENV: &str = "KEEPASS_2_FILE_OUTPUT";
+pub const TEMPLATE_ENV: &str = "KEEPASS_2_FILE_TEMPLATE";
+
+#[derive(Debug, Clone, Copy)]
+pub enum HookStage {
+    PreBuild,
+    PostBuild,
+    OnChange,
+}
+
+impl std::fmt::Display for HookStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::PreBuild => "pre_build",
+            Self::PostBuild => "post_build",
+            Self::OnChange => "on_change",
+        };
+        f.write_str(text)
+    }
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut process = Command::new("cmd");
+    process.args(["/C", command]);
+    process
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut process = Command::new("sh");
+    process.args(["-c", command]);
+    process
+}
+
+/// Runs the command in the shell of the system inside the folder, with the
+/// paths of the template and the output in the environment
+pub fn run_hook(
+    io: &dyn IOLogs,
+    command: &str,
+    dir: &Path,
+    template: &str,
+    output: &str,
+) -> Result<(), Box<dyn Error>> {
+    let result = shell_command(command)
+        .current_dir(dir)
+        .env(OUTPUT_ENV, output)
+        .env(TEMPLATE_ENV, template)
+        .output()?;
+
+    for line in String::from_utf8_lossy(&result.stdout).lines() {
+        io.log(line.to_string());
+    }
+    for line in String::from_utf8_lossy(&result.stderr).lines() {
+        io.error(line.to_string());
+    }
+
+    if result.status.success() {
+        Ok(())
+    } else {
+        Err(format!("Command '{command}' finished with {}", result.status).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::test_helpers::tests::IODebug;
+
+    use super::*;
+
+    #[test]
+    fn test_run_hook_logs_output() {
+        let io = IODebug::new();
+        let result = run_hook(&io, "echo done", Path::new("."), "template.hbs", ".env");
+
+        assert!(result.is_ok());
+        assert_eq!(io.get_logs(), vec!["done"]);
+    }
+
+    #[test]
+    fn test_run_hook_failure() {
+        let io = IODebug::new();
+        let result = run_hook(&io, "exit 3", Path::new("."), "template.hbs", ".env");
+
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .starts_with("Command 'exit 3' finished with")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_hook_environment() {
+        let io = IODebug::new();
+        let result = run_hook(
+            &io,
+            "echo $KEEPASS_2_FILE_TEMPLATE $KEEPASS_2_FILE_OUTPUT >&2",
+            Path::new("."),
+            "template.hbs",
+            ".env",
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(io.get_errors(), vec!["template.hbs .env"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_hook_directory() {
+        let io = IODebug::new();
+        let result = run_hook(&io, "pwd", Path::new("test_resources"), "t.hbs", ".env");
+
+        assert!(result.is_ok());
+        assert!(io.get_logs()[0].ends_with("/test_resources"));
+    }
+}
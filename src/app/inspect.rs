@@ -0,0 +1,391 @@
+use std::error::Error;
+
+use handlebars::{
+    Helper, JsonValue, Path, PathAndJson, TemplateError,
+    template::{HelperTemplate, Parameter, Template, TemplateElement, TemplateMapping},
+};
+
+use super::front_matter::{front_matter_lines, split_front_matter};
+
+pub const KEEPASS_HELPER: &str = "keepass";
+pub const KEEPASS_URL_HELPER: &str = "keepass_url";
+
+/// Call of the keepass or keepass_url helpers found in the source of a template
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeepassCall {
+    /// Name of the helper, keepass or keepass_url
+    pub helper: String,
+    pub path: Vec<String>,
+    /// Name of the field, password when it is not set. The keepass_url helper
+    /// reads the username, the password and the URL
+    pub field: String,
+    /// Position of the expression, starting at 1
+    pub line: usize,
+    pub column: usize,
+}
+
+impl KeepassCall {
+    /// What the call reads from the entry, to list it
+    pub fn reads(&self) -> &str {
+        if self.helper == KEEPASS_URL_HELPER {
+            KEEPASS_URL_HELPER
+        } else {
+            &self.field
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableReference {
+    pub name: String,
+    /// The value is written in the output, not only passed to a helper
+    pub printed: bool,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// What a template needs to be built, read without rendering it
+#[derive(Debug, Default, PartialEq)]
+pub struct TemplateReferences {
+    pub keepass: Vec<KeepassCall>,
+    /// Only the first use of each variable
+    pub variables: Vec<VariableReference>,
+    pub partials: Vec<String>,
+}
+
+/// The helpers that change the context, the paths inside them are not
+/// variables of the build
+const CONTEXT_HELPERS: [&str; 2] = ["each", "with"];
+
+fn literal(parameter: &Parameter) -> Option<String> {
+    match parameter {
+        Parameter::Literal(JsonValue::String(value)) => Some(value.clone()),
+        Parameter::Literal(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Name of the variable, skipping this, the local paths like @index and the
+/// parent paths. The paths of @root are always variables
+fn variable_name(path: &Path, nested: bool) -> Option<String> {
+    let Path::Relative((_, raw)) = path else {
+        return None;
+    };
+    if let Some(name) = raw.strip_prefix("@root.") {
+        return Some(name.to_string());
+    }
+    let local = raw == "this" || raw.starts_with("this.") || raw.starts_with("this/");
+    if nested || local || raw.starts_with('.') || raw.starts_with('@') || raw.is_empty() {
+        return None;
+    }
+    Some(raw.clone())
+}
+
+struct Collector {
+    references: TemplateReferences,
+}
+
+impl Collector {
+    fn add_variable(
+        &mut self,
+        path: &Path,
+        nested: bool,
+        printed: bool,
+        position: &TemplateMapping,
+    ) {
+        let Some(name) = variable_name(path, nested) else {
+            return;
+        };
+        let variables = &mut self.references.variables;
+        match variables.iter_mut().find(|variable| variable.name == name) {
+            Some(variable) => variable.printed |= printed,
+            None => variables.push(VariableReference {
+                name,
+                printed,
+                line: position.0,
+                column: position.1,
+            }),
+        }
+    }
+
+    fn add_keepass_call(
+        &mut self,
+        helper: &str,
+        params: &[Parameter],
+        hash: &std::collections::HashMap<String, Parameter>,
+        position: &TemplateMapping,
+    ) {
+        // Paths only known when rendering, like variables, can't be listed
+        let Some(path) = params.iter().map(literal).collect::<Option<Vec<String>>>() else {
+            return;
+        };
+        // A single parameter is a path, more of them are the deprecated format
+        let path = match &path[..] {
+            [] => return,
+            [path] => path.split('/').map(String::from).collect(),
+            _ => path,
+        };
+        let field = match helper {
+            KEEPASS_URL_HELPER => String::from("url"),
+            _ => hash
+                .get("field")
+                .and_then(|field| literal(field).or_else(|| field.as_name().map(String::from)))
+                .unwrap_or_else(|| String::from("password")),
+        };
+        self.references.keepass.push(KeepassCall {
+            helper: helper.to_string(),
+            path,
+            field,
+            line: position.0,
+            column: position.1,
+        });
+    }
+
+    fn parameter(&mut self, parameter: &Parameter, nested: bool, position: &TemplateMapping) {
+        match parameter {
+            Parameter::Path(path) => self.add_variable(path, nested, false, position),
+            Parameter::Subexpression(subexpression) => {
+                self.element(subexpression.as_element(), nested, position)
+            }
+            _ => {}
+        }
+    }
+
+    fn element(&mut self, element: &TemplateElement, nested: bool, position: &TemplateMapping) {
+        match element {
+            TemplateElement::Expression(helper)
+            | TemplateElement::HtmlExpression(helper)
+            | TemplateElement::HelperBlock(helper) => {
+                let name = helper.name.as_name().unwrap_or_default();
+                let name_only = !helper.block && helper.params.is_empty() && helper.hash.is_empty();
+                if name_only {
+                    if let Parameter::Path(path) = &helper.name {
+                        self.add_variable(path, nested, true, position);
+                    }
+                    return;
+                }
+                if name == KEEPASS_HELPER || name == KEEPASS_URL_HELPER {
+                    self.add_keepass_call(name, &helper.params, &helper.hash, position);
+                }
+                for parameter in helper.params.iter().chain(helper.hash.values()) {
+                    self.parameter(parameter, nested, position);
+                }
+                let nested = nested || CONTEXT_HELPERS.contains(&name);
+                if let Some(template) = &helper.template {
+                    self.template(template, nested);
+                }
+                if let Some(inverse) = &helper.inverse {
+                    self.template(inverse, nested);
+                }
+            }
+            TemplateElement::PartialExpression(partial)
+            | TemplateElement::PartialBlock(partial) => {
+                let name = match &partial.name {
+                    Parameter::Name(name) => Some(name.clone()),
+                    Parameter::Path(Path::Relative((_, raw))) => Some(raw.clone()),
+                    parameter => literal(parameter),
+                };
+                if let Some(name) = name
+                    && !self.references.partials.contains(&name)
+                {
+                    self.references.partials.push(name);
+                }
+                for parameter in partial.params.iter().chain(partial.hash.values()) {
+                    self.parameter(parameter, nested, position);
+                }
+                if let Some(template) = &partial.template {
+                    self.template(template, nested);
+                }
+            }
+            // Decorators, comments and the text don't reference anything
+            _ => {}
+        }
+    }
+
+    fn template(&mut self, template: &Template, nested: bool) {
+        for (element, position) in template.elements.iter().zip(&template.mapping) {
+            self.element(element, nested, position);
+        }
+    }
+}
+
+/// The value of a parameter comes from it, the subexpressions are only known
+/// when rendering so they match any value
+fn same_parameter(parameter: &Parameter, value: &PathAndJson) -> bool {
+    match parameter {
+        Parameter::Literal(literal) => value.relative_path().is_none() && value.value() == literal,
+        Parameter::Name(name) => value.relative_path() == Some(name),
+        Parameter::Path(Path::Relative((_, raw)) | Path::Local((_, _, raw))) => {
+            value.relative_path() == Some(raw)
+        }
+        _ => true,
+    }
+}
+
+fn same_call(helper: &HelperTemplate, h: &Helper) -> bool {
+    helper.name.as_name() == Some(h.name())
+        && helper.params.len() == h.params().len()
+        && helper.hash.len() == h.hash().len()
+        && helper
+            .params
+            .iter()
+            .zip(h.params())
+            .all(|(parameter, value)| same_parameter(parameter, value))
+        && helper.hash.iter().all(|(key, parameter)| {
+            h.hash_get(key)
+                .is_some_and(|value| same_parameter(parameter, value))
+        })
+}
+
+fn element_positions(
+    element: &TemplateElement,
+    position: &TemplateMapping,
+    h: &Helper,
+    positions: &mut Vec<(usize, usize)>,
+) {
+    let (parameters, templates): (Vec<&Parameter>, Vec<&Option<Template>>) = match element {
+        TemplateElement::Expression(helper)
+        | TemplateElement::HtmlExpression(helper)
+        | TemplateElement::HelperBlock(helper) => {
+            if same_call(helper, h) {
+                positions.push((position.0, position.1));
+                return;
+            }
+            (
+                helper.params.iter().chain(helper.hash.values()).collect(),
+                vec![&helper.template, &helper.inverse],
+            )
+        }
+        TemplateElement::PartialExpression(partial) | TemplateElement::PartialBlock(partial) => (
+            partial.params.iter().chain(partial.hash.values()).collect(),
+            vec![&partial.template],
+        ),
+        _ => return,
+    };
+    for parameter in parameters {
+        if let Parameter::Subexpression(subexpression) = parameter {
+            element_positions(subexpression.as_element(), position, h, positions);
+        }
+    }
+    for template in templates.into_iter().flatten() {
+        template_positions(template, h, positions);
+    }
+}
+
+fn template_positions(template: &Template, h: &Helper, positions: &mut Vec<(usize, usize)>) {
+    for (element, position) in template.elements.iter().zip(&template.mapping) {
+        element_positions(element, position, h, positions);
+    }
+}
+
+/// Positions of the calls in the template with the same parameters of the
+/// helper, in the order they are written. The helpers are not told where
+/// they are called from
+pub fn helper_positions(template: &Template, h: &Helper) -> Vec<(usize, usize)> {
+    let mut positions = Vec::new();
+    template_positions(template, h, &mut positions);
+    positions
+}
+
+/// Lists the keepass calls, variables and partials of a template, the
+/// positions are relative to the source given
+pub fn inspect_template(source: &str) -> Result<TemplateReferences, TemplateError> {
+    let template = Template::compile(source)?;
+    let mut collector = Collector {
+        references: TemplateReferences::default(),
+    };
+    collector.template(&template, false);
+    Ok(collector.references)
+}
+
+/// Reads a template file without its front-matter, the positions are lines
+/// of the file
+pub fn inspect_file(template: &str) -> Result<TemplateReferences, Box<dyn Error>> {
+    let source = std::fs::read_to_string(template)
+        .map_err(|e| format!("Failed to read template {template}: {e}"))?;
+    let (_, body) = split_front_matter(&source)?;
+    let offset = front_matter_lines(&source, body);
+    let mut references =
+        inspect_template(body).map_err(|e| format!("Invalid template {template}: {e}"))?;
+    for call in &mut references.keepass {
+        call.line += offset;
+    }
+    for variable in &mut references.variables {
+        variable.line += offset;
+    }
+    Ok(references)
+}
+
+/// Calls of the keepass and keepass_url helpers, empty when the template is
+/// not valid
+pub fn find_keepass_calls(source: &str) -> Vec<KeepassCall> {
+    inspect_template(source)
+        .map(|references| references.keepass)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_keepass_calls() {
+        let template = "A={{keepass \"group1/test2\"}}\n\
+            B={{ keepass field=username \"test1\" }} C={{stringify (keepass 'complex' field=\"url\")}}\n\
+            D={{keepass entry}} E={{keepass_url \"complex\" scheme=\"http\"}} F={{keepass \"group1\" \"test3\"}}";
+
+        let calls: Vec<(String, String, usize, usize)> = find_keepass_calls(template)
+            .into_iter()
+            .map(|call| {
+                let reads = call.reads().to_string();
+                (call.path.join("/"), reads, call.line, call.column)
+            })
+            .collect();
+
+        assert_eq!(
+            calls,
+            vec![
+                (String::from("group1/test2"), String::from("password"), 1, 3),
+                (String::from("test1"), String::from("username"), 2, 3),
+                (String::from("complex"), String::from("url"), 2, 42),
+                (String::from("complex"), String::from("keepass_url"), 3, 23),
+                (
+                    String::from("group1/test3"),
+                    String::from("password"),
+                    3,
+                    65
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inspect_template() {
+        let template = "{{> header}}\n\
+            HOST={{host}} PORT={{default port 5432}}\n\
+            {{#if @root.debug}}DEBUG=1{{/if}}\n\
+            {{#each services}}{{name}}={{keepass \"services\" this}}{{/each}}\n\
+            {{#> layout title=app.title}}{{host}}{{/layout}}";
+
+        let references = inspect_template(template).unwrap();
+
+        let variables: Vec<(&str, bool, usize)> = references
+            .variables
+            .iter()
+            .map(|variable| (variable.name.as_str(), variable.printed, variable.line))
+            .collect();
+        assert_eq!(
+            variables,
+            vec![
+                ("host", true, 2),
+                ("port", false, 2),
+                ("debug", false, 3),
+                ("services", false, 4),
+                ("app.title", false, 5)
+            ]
+        );
+        assert_eq!(references.partials, vec!["header", "layout"]);
+        assert!(references.keepass.is_empty());
+        assert!(inspect_template("{{#if a}}").is_err());
+    }
+}
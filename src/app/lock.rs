@@ -0,0 +1,487 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    io::{ErrorKind, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use hmac::{Hmac, Mac};
+use keepass::db::Entry;
+use rand::{Rng, rngs::OsRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{config::yaml::Variables, encoding::to_hex};
+
+/// Version of an entry used by an output, none of its values are kept
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntryVersion {
+    pub uuid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+}
+
+impl From<&Entry> for EntryVersion {
+    fn from(entry: &Entry) -> Self {
+        Self {
+            uuid: entry.id().uuid().to_string(),
+            modified: entry
+                .times
+                .last_modification
+                .map(|modified| modified.to_string()),
+        }
+    }
+}
+
+/// Inputs of the last build of an output
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LockedOutput {
+    pub template: String,
+    /// Hash of the template and of the partials it uses
+    pub template_hash: String,
+    /// Keyed hash of the variables, with the env: references resolved
+    pub variables_hash: String,
+    /// Hash of the escaping, mode, encryption and generator of the output
+    #[serde(default)]
+    pub options_hash: String,
+    /// Entries read by the helpers, by their path in the database
+    #[serde(default)]
+    pub entries: BTreeMap<String, EntryVersion>,
+    /// Keyed hash of the environment variables and files read by the helpers
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, String>,
+    /// Keyed hash of the rendered output before it is encrypted, an encrypted
+    /// output is different on every build
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub output_hash: String,
+}
+
+impl LockedOutput {
+    /// Why the output differs from the current inputs, the entries are looked
+    /// up with the callback. Empty when it is up to date
+    pub fn changes(
+        &self,
+        current: &LockedOutput,
+        key: &LockKey,
+        entry_version: impl Fn(&str) -> Option<EntryVersion>,
+    ) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.template != current.template || self.template_hash != current.template_hash {
+            changes.push(String::from("the template changed"));
+        }
+        if self.variables_hash != current.variables_hash {
+            changes.push(String::from("the variables changed"));
+        }
+        if self.options_hash != current.options_hash {
+            changes.push(String::from("the options of the output changed"));
+        }
+        for (path, version) in &self.entries {
+            match entry_version(path) {
+                Some(current) if current == *version => {}
+                Some(_) => changes.push(format!("the entry {path} changed")),
+                None => changes.push(format!("the entry {path} was removed")),
+            }
+        }
+        for (input, hash) in &self.inputs {
+            if current_input_hash(key, input) != *hash {
+                changes.push(match input.split_once(':') {
+                    Some((ENV_INPUT, name)) => format!("the environment variable {name} changed"),
+                    Some((_, file)) => format!("the file {file} changed"),
+                    None => format!("{input} changed"),
+                });
+            }
+        }
+        changes
+    }
+}
+
+const LOCK_KEY_LENGTH: usize = 32;
+
+/// Key of the hashes of the values that can be secret: the variables, the
+/// environment variables and files read by the helpers and the rendered
+/// outputs. It is random and kept next to the global configuration, out of
+/// the project, so the hashes of the lock file cannot be checked against
+/// guessed values
+#[derive(Clone)]
+pub struct LockKey(Arc<[u8; LOCK_KEY_LENGTH]>);
+
+impl Default for LockKey {
+    /// A new random key, the hashes made with it are only valid in this run
+    fn default() -> Self {
+        let mut key = [0; LOCK_KEY_LENGTH];
+        OsRng.fill(&mut key);
+        Self(Arc::new(key))
+    }
+}
+
+impl LockKey {
+    /// Reads the key of the file, which is created the first time only
+    /// readable by the user
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let invalid =
+            |e: &dyn std::fmt::Display| format!("Invalid lock key {}: {e}", path.display());
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                let key = STANDARD
+                    .decode(contents.trim())
+                    .map_err(|e| invalid(&e))?
+                    .try_into()
+                    .map_err(|_| invalid(&"wrong length"))?;
+                Ok(Self(Arc::new(key)))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let key = Self::default();
+                let mut options = std::fs::OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+                options
+                    .open(path)
+                    .and_then(|mut file| file.write_all(STANDARD.encode(*key.0).as_bytes()))
+                    .map_err(|e| format!("Cannot write the lock key {}: {e}", path.display()))?;
+                Ok(key)
+            }
+            Err(e) => Err(format!("Cannot read the lock key {}: {e}", path.display()).into()),
+        }
+    }
+
+    /// HMAC-SHA256 of the value
+    pub fn hash(&self, value: &[u8]) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&*self.0).expect("HMAC accepts keys of any length");
+        mac.update(value);
+        to_hex(&mac.finalize().into_bytes())
+    }
+}
+
+const ENV_INPUT: &str = "env";
+const FILE_INPUT: &str = "file";
+
+/// Hash of a value read by the helpers, empty when it doesn't exist
+fn input_hash(key: &LockKey, value: Option<&[u8]>) -> String {
+    value.map(|value| key.hash(value)).unwrap_or_default()
+}
+
+/// Hash of the value an input has now, like env:HOME or file:config.json
+fn current_input_hash(key: &LockKey, input: &str) -> String {
+    match input.split_once(':') {
+        Some((ENV_INPUT, name)) => {
+            input_hash(key, std::env::var(name).ok().as_deref().map(str::as_bytes))
+        }
+        Some((FILE_INPUT, file)) => input_hash(key, std::fs::read(file).ok().as_deref()),
+        _ => String::new(),
+    }
+}
+
+/// Environment variables and files read by the helpers while rendering, only
+/// their keyed hash is kept
+#[derive(Clone)]
+pub struct ReadInputs {
+    key: LockKey,
+    inputs: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl ReadInputs {
+    pub fn new(key: LockKey) -> Self {
+        Self {
+            key,
+            inputs: Arc::default(),
+        }
+    }
+
+    pub fn record_env(&self, name: &str, value: Option<&str>) {
+        let hash = input_hash(&self.key, value.map(str::as_bytes));
+        self.inputs
+            .lock()
+            .unwrap()
+            .insert(format!("{ENV_INPUT}:{name}"), hash);
+    }
+
+    pub fn record_file(&self, file: &Path, contents: &str) {
+        let hash = input_hash(&self.key, Some(contents.as_bytes()));
+        self.inputs
+            .lock()
+            .unwrap()
+            .insert(format!("{FILE_INPUT}:{}", file.display()), hash);
+    }
+
+    /// Returns the inputs read since the last call
+    pub fn take(&self) -> BTreeMap<String, String> {
+        std::mem::take(&mut *self.inputs.lock().unwrap())
+    }
+}
+
+/// Keyed hashes of the rendered outputs by their path, the encrypted outputs
+/// are different on every build so they are compared with these
+#[derive(Default)]
+pub struct OutputHashes {
+    key: LockKey,
+    hashes: HashMap<String, String>,
+}
+
+impl OutputHashes {
+    pub fn new(key: LockKey) -> Self {
+        Self {
+            key,
+            hashes: HashMap::new(),
+        }
+    }
+
+    /// Hash of the last build of the output
+    pub fn get(&self, output: &str) -> Option<&str> {
+        self.hashes.get(output).map(String::as_str)
+    }
+
+    /// Sets the hash of a previous build, like the one of the lock file
+    pub fn insert(&mut self, output: String, hash: String) {
+        self.hashes.insert(output, hash);
+    }
+
+    /// Keeps the hash of the new contents of the output, true when they are
+    /// different from the ones of the last build or it is unknown
+    pub fn update(&mut self, output: &str, rendered: &str) -> bool {
+        let hash = self.key.hash(rendered.as_bytes());
+        self.hashes.insert(output.to_string(), hash.clone()) != Some(hash)
+    }
+}
+
+/// Contents of the lock file, the outputs are identified by their path. The
+/// values that can be secret are hashed with the LockKey of the machine, so
+/// the lock is only valid where it was written
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LockFile {
+    #[serde(default)]
+    pub outputs: BTreeMap<String, LockedOutput>,
+}
+
+impl LockFile {
+    /// An empty lock is returned when the file doesn't exist
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read the lock file {}: {e}", path.display()))?;
+        Ok(serde_yaml::from_str(&contents)
+            .map_err(|e| format!("Invalid lock file {}: {e}", path.display()))?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, serde_yaml::to_string(self)?)
+            .map_err(|e| format!("Cannot write the lock file {}: {e}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Hash of the contents of the files, in the order of their paths. A file
+/// that cannot be read is hashed as empty
+pub fn files_hash(files: &[String]) -> String {
+    let mut files = files.to_vec();
+    files.sort();
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(Sha256::digest(std::fs::read(&file).unwrap_or_default()));
+    }
+    to_hex(&hasher.finalize())
+}
+
+/// The variables can hold secrets, like the env: references resolved
+pub fn variables_hash(key: &LockKey, variables: &Variables) -> String {
+    let json = serde_json::to_string(variables).unwrap_or_default();
+    key.hash(json.as_bytes())
+}
+
+pub fn options_hash(options: &impl Serialize) -> String {
+    let json = serde_json::to_string(options).unwrap_or_default();
+    to_hex(&Sha256::digest(json.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use test_helpers::TmpFile;
+
+    use super::*;
+
+    fn version(uuid: &str, modified: &str) -> EntryVersion {
+        EntryVersion {
+            uuid: uuid.to_string(),
+            modified: Some(modified.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_locked_output_changes() {
+        let locked = LockedOutput {
+            template: String::from("app.env.hbs"),
+            template_hash: String::from("t1"),
+            variables_hash: String::from("v1"),
+            options_hash: String::from("o1"),
+            entries: BTreeMap::from([
+                (String::from("db"), version("1", "2024-01-01 10:00:00")),
+                (
+                    String::from("group/api"),
+                    version("2", "2024-01-01 10:00:00"),
+                ),
+            ]),
+            inputs: BTreeMap::new(),
+            output_hash: String::from("r1"),
+        };
+        let unchanged = |path: &str| match path {
+            "db" => Some(version("1", "2024-01-01 10:00:00")),
+            "group/api" => Some(version("2", "2024-01-01 10:00:00")),
+            _ => None,
+        };
+        let current = LockedOutput {
+            entries: BTreeMap::new(),
+            ..locked.clone()
+        };
+        let key = LockKey::default();
+        assert!(locked.changes(&current, &key, unchanged).is_empty());
+
+        let edited = |path: &str| match path {
+            "db" => Some(version("1", "2024-02-01 08:30:00")),
+            _ => None,
+        };
+        assert_eq!(
+            locked.changes(
+                &LockedOutput {
+                    template_hash: String::from("t2"),
+                    variables_hash: String::from("v2"),
+                    options_hash: String::from("o2"),
+                    ..current
+                },
+                &key,
+                edited
+            ),
+            vec![
+                "the template changed",
+                "the variables changed",
+                "the options of the output changed",
+                "the entry db changed",
+                "the entry group/api was removed"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_inputs_changes() {
+        let file = TmpFile::new_uuid("test_resources/tmp", Some("pem"));
+        file.write(String::from("first"));
+        let key = LockKey::default();
+        let reads = ReadInputs::new(key.clone());
+        reads.record_file(Path::new(&file.get()), "first");
+        reads.record_env("KEEPASS_2_FILE_MISSING", None);
+        reads.record_env("CARGO_PKG_NAME", Some("other-name"));
+        let locked = LockedOutput {
+            inputs: reads.take(),
+            ..LockedOutput::default()
+        };
+        assert!(reads.take().is_empty());
+
+        assert_eq!(
+            locked.changes(&LockedOutput::default(), &key, |_| None),
+            vec!["the environment variable CARGO_PKG_NAME changed"]
+        );
+        // The hashes are only valid with the same key
+        assert_eq!(
+            locked
+                .changes(&LockedOutput::default(), &LockKey::default(), |_| None)
+                .len(),
+            2
+        );
+        file.write(String::from("second"));
+        assert_eq!(
+            locked.changes(&LockedOutput::default(), &key, |_| None),
+            vec![
+                "the environment variable CARGO_PKG_NAME changed".to_string(),
+                format!("the file {} changed", file.get())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hashes() {
+        let variables = Variables::from_iter([(String::from("port"), json!(80))]);
+        let other = Variables::from_iter([(String::from("port"), json!(81))]);
+        let key = LockKey::default();
+        assert_eq!(
+            variables_hash(&key, &variables),
+            variables_hash(&key, &variables.clone())
+        );
+        assert_ne!(
+            variables_hash(&key, &variables),
+            variables_hash(&key, &other)
+        );
+        assert_ne!(
+            variables_hash(&key, &variables),
+            variables_hash(&LockKey::default(), &variables)
+        );
+
+        let options = json!({"escape": "json", "mode": 384});
+        assert_eq!(options_hash(&options), options_hash(&options.clone()));
+        assert_ne!(
+            options_hash(&options),
+            options_hash(&json!({"escape": "json"}))
+        );
+
+        let mut outputs = OutputHashes::new(key);
+        assert!(outputs.update("app.env", "A=1"));
+        assert!(!outputs.update("app.env", "A=1"));
+        assert!(outputs.update("app.env", "A=2"));
+
+        let template = TmpFile::new_uuid("test_resources/tmp", Some("hbs"));
+        template.write(String::from("{{port}}"));
+        let files = vec![template.get(), String::from("test_resources/missing.hbs")];
+        let reversed: Vec<String> = files.iter().rev().cloned().collect();
+        assert_eq!(files_hash(&files), files_hash(&reversed));
+        let hash = files_hash(&files);
+        template.write(String::from("{{host}}"));
+        assert_ne!(files_hash(&files), hash);
+    }
+
+    #[test]
+    fn test_lock_key() {
+        let file = TmpFile::new_uuid("test_resources/tmp", Some("key"));
+        let path = Path::new(&file.get()).to_path_buf();
+        let key = LockKey::load(&path).unwrap();
+        assert_eq!(LockKey::load(&path).unwrap().hash(b"a"), key.hash(b"a"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        file.write(String::from("short"));
+        assert!(
+            LockKey::load(&path)
+                .err()
+                .unwrap()
+                .to_string()
+                .starts_with("Invalid lock key")
+        );
+    }
+
+    #[test]
+    fn test_lock_file_round_trip() {
+        let file = TmpFile::new_uuid("test_resources/tmp", Some("lock"));
+        let path = Path::new(&file.get()).to_path_buf();
+        assert_eq!(LockFile::load(&path).unwrap(), LockFile::default());
+
+        let lock = LockFile {
+            outputs: BTreeMap::from([(
+                String::from(".env"),
+                LockedOutput {
+                    template: String::from(".env.hbs"),
+                    entries: BTreeMap::from([(String::from("db"), version("1", "now"))]),
+                    ..LockedOutput::default()
+                },
+            )]),
+        };
+        lock.save(&path).unwrap();
+        assert_eq!(LockFile::load(&path).unwrap(), lock);
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use super::{handlebars::LibHandlebars, inspect::inspect_template};
+
+const PARTIAL_EXTENSIONS: [&str; 3] = ["", "hbs", "handlebars"];
+
+/// Name of the partial for a file inside the partials folder, the relative
+/// path without the extension, like common/database for common/database.hbs
+fn partial_name(dir: &Path, file: &Path) -> Option<String> {
+    let relative = file.strip_prefix(dir).ok()?.with_extension("");
+    let parts: Vec<String> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Some(parts.join("/"))
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Registers every file of the folder as a partial, the files of the
+/// subfolders are registered as folder/name
+pub fn register_partials_dir(
+    handlebars: &mut LibHandlebars,
+    dir: &str,
+) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(dir);
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)
+        .map_err(|e| format!("Cannot read the partials folder {}: {e}", dir.display()))?;
+    for file in files {
+        if let Some(name) = partial_name(dir, &file) {
+            handlebars
+                .register_template_file(&name, &file)
+                .map_err(|e| format!("Invalid partial {}: {e}", file.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Names of the partials used in a template, the dynamic partials are ignored.
+/// An invalid template has none, its error is reported when it is rendered
+pub fn partial_names(template: &str) -> Vec<String> {
+    inspect_template(template)
+        .map(|references| references.partials)
+        .unwrap_or_default()
+}
+
+fn find_relative_partial(dir: &Path, name: &str) -> Option<PathBuf> {
+    PARTIAL_EXTENSIONS
+        .iter()
+        .map(|extension| {
+            if extension.is_empty() {
+                dir.join(name)
+            } else {
+                dir.join(format!("{name}.{extension}"))
+            }
+        })
+        .find(|path| path.is_file())
+}
+
+/// File of a partial used by a template, the same one the build registers:
+/// the last partials folder that has it, or the folder of the template
+pub fn find_partial(dirs: &[String], template_path: &str, name: &str) -> Option<PathBuf> {
+    let template_dir = Path::new(template_path)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+    let in_dirs = dirs
+        .iter()
+        .rev()
+        .find_map(|dir| find_relative_partial(Path::new(dir), name));
+    in_dirs.or_else(|| find_relative_partial(&template_dir, name))
+}
+
+/// Registers the partials used by the template that are not registered yet,
+/// looking for them relative to the folder of the template. Returns the
+/// names registered, to remove them once the template is rendered
+pub fn register_relative_partials(
+    handlebars: &mut LibHandlebars,
+    template_path: &str,
+    template: &str,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let dir = Path::new(template_path)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+    let mut registered = Vec::new();
+    let mut pending = partial_names(template);
+    while let Some(name) = pending.pop() {
+        if handlebars.has_template(&name) || registered.contains(&name) {
+            continue;
+        }
+        let Some(path) = find_relative_partial(&dir, &name) else {
+            continue;
+        };
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read partial {}: {e}", path.display()))?;
+        handlebars
+            .register_partial(&name, &source)
+            .map_err(|e| format!("Invalid partial {}: {e}", path.display()))?;
+        pending.extend(partial_names(&source));
+        registered.push(name);
+    }
+    Ok(registered)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_helpers::TmpFile;
+
+    use super::*;
+
+    #[test]
+    fn test_partial_names() {
+        let template = "{{> header}}\n{{~> common/database ~}}\n{{#> layout}}x{{/layout}}\n{{> (dynamic)}}\n{{> header}}{{value}}\n{{!-- {{> commented}} --}}\n{{{{raw}}}}{{> raw}}{{{{/raw}}}}";
+
+        assert_eq!(
+            partial_names(template),
+            vec!["header", "common/database", "layout"]
+        );
+    }
+
+    #[test]
+    fn test_register_partials_dir() {
+        let dir = TmpFile::new_uuid("test_resources/tmp", None::<String>);
+        std::fs::create_dir_all(format!("{}/common", dir.get())).unwrap();
+        std::fs::write(format!("{}/common/database.hbs", dir.get()), "DB={{db}}\n").unwrap();
+        std::fs::write(format!("{}/header", dir.get()), "# {{name}}\n").unwrap();
+
+        let mut handlebars = LibHandlebars::new();
+        register_partials_dir(&mut handlebars, &dir.get()).unwrap();
+
+        let rendered = handlebars
+            .render_template(
+                "{{> header}}\n{{> common/database}}",
+                &serde_json::json!({"name": "api", "db": "main"}),
+            )
+            .unwrap();
+        assert_eq!(rendered, "# api\nDB=main\n");
+    }
+
+    #[test]
+    fn test_find_partial() {
+        let dir = TmpFile::new_uuid("test_resources/tmp", None::<String>);
+        let (global, project) = (
+            format!("{}/global", dir.get()),
+            format!("{}/project", dir.get()),
+        );
+        std::fs::create_dir_all(&global).unwrap();
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(format!("{global}/header.hbs"), "").unwrap();
+        std::fs::write(format!("{project}/header.hbs"), "").unwrap();
+        std::fs::write(format!("{global}/footer"), "").unwrap();
+        std::fs::write(format!("{}/local.hbs", dir.get()), "").unwrap();
+        let dirs = [global.clone(), project.clone()];
+        let template = format!("{}/app.hbs", dir.get());
+
+        let found = |name| find_partial(&dirs, &template, name);
+
+        assert_eq!(
+            found("header"),
+            Some(PathBuf::from(format!("{project}/header.hbs")))
+        );
+        assert_eq!(
+            found("footer"),
+            Some(PathBuf::from(format!("{global}/footer")))
+        );
+        assert_eq!(
+            found("local"),
+            Some(PathBuf::from(format!("{}/local.hbs", dir.get())))
+        );
+        assert_eq!(found("unknown"), None);
+    }
+
+    #[test]
+    fn test_register_relative_partials() {
+        let dir = TmpFile::new_uuid("test_resources/tmp", None::<String>);
+        std::fs::create_dir_all(format!("{}/parts", dir.get())).unwrap();
+        std::fs::write(format!("{}/parts/db.hbs", dir.get()), "{{> parts/user}}").unwrap();
+        std::fs::write(format!("{}/parts/user.hbs", dir.get()), "USER=admin").unwrap();
+        let template = "{{> parts/db}}";
+
+        let mut handlebars = LibHandlebars::new();
+        let mut registered = register_relative_partials(
+            &mut handlebars,
+            &format!("{}/app.hbs", dir.get()),
+            template,
+        )
+        .unwrap();
+        registered.sort();
+
+        assert_eq!(registered, vec!["parts/db", "parts/user"]);
+        assert_eq!(
+            handlebars.render_template(template, &()).unwrap(),
+            "USER=admin"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::error::Error;
+
+use serde_json::Value;
+
+use super::{
+    IOLogs,
+    config::yaml::{RequiredVariables, Variables, YamlConfigRequiredVariable},
+};
+
+pub struct RequiredAnswer {
+    pub name: String,
+    pub value: Value,
+    /// The value was written by the user and it is not a secret
+    pub can_be_saved: bool,
+}
+
+fn prompt(name: &str, required: &YamlConfigRequiredVariable) -> String {
+    let mut prompt = String::from(name);
+    if let Some(description) = &required.description {
+        prompt.push_str(&format!(" ({description})"));
+    }
+    if let Some(default) = &required.default {
+        prompt.push_str(&format!(" [{default}]"));
+    }
+    prompt.push_str(": ");
+    prompt
+}
+
+fn describe(name: &str, required: &YamlConfigRequiredVariable) -> String {
+    match &required.description {
+        Some(description) => format!("{name} ({description})"),
+        None => name.to_string(),
+    }
+}
+
+/// Required variables without value nor default, described for the logs
+pub fn missing_required_variables(
+    required: &RequiredVariables,
+    variables: &Variables,
+) -> Vec<String> {
+    required
+        .iter()
+        .filter(|(name, settings)| {
+            settings.default.is_none() && variables.get(*name).is_none_or(Value::is_null)
+        })
+        .map(|(name, settings)| describe(name, settings))
+        .collect()
+}
+
+/// Defaults of the required variables without value, for the commands that
+/// don't ask for them
+pub fn required_defaults(required: &RequiredVariables, variables: &Variables) -> Variables {
+    required
+        .iter()
+        .filter(|(name, _)| variables.get(*name).is_none_or(Value::is_null))
+        .filter_map(|(name, settings)| Some((name.clone(), settings.default.clone()?)))
+        .collect()
+}
+
+/// Looks for the required variables without value, using their default or
+/// asking for them when running in a terminal
+pub fn ask_missing_variables(
+    io: &dyn IOLogs,
+    required: &RequiredVariables,
+    variables: &Variables,
+) -> Result<Vec<RequiredAnswer>, Box<dyn Error>> {
+    let missing = required
+        .iter()
+        .filter(|(name, _)| variables.get(*name).is_none_or(Value::is_null));
+
+    let mut answers = vec![];
+    let mut unanswered = vec![];
+    for (name, settings) in missing {
+        let answer = if io.is_interactive() {
+            io.read(prompt(name, settings), settings.secret)?
+        } else {
+            String::new()
+        };
+        match (answer.is_empty(), &settings.default) {
+            (false, _) => answers.push(RequiredAnswer {
+                name: name.clone(),
+                value: Value::from(answer),
+                can_be_saved: !settings.secret,
+            }),
+            (true, Some(default)) => answers.push(RequiredAnswer {
+                name: name.clone(),
+                value: default.clone(),
+                can_be_saved: false,
+            }),
+            (true, None) => unanswered.push(describe(name, settings)),
+        }
+    }
+
+    if unanswered.is_empty() {
+        Ok(answers)
+    } else {
+        Err(format!("Missing required variables: {}", unanswered.join(", ")).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::app::test_helpers::tests::IODebug;
+
+    fn required() -> RequiredVariables {
+        RequiredVariables::from([
+            (
+                String::from("email"),
+                YamlConfigRequiredVariable {
+                    description: Some(String::from("Your e-mail")),
+                    ..YamlConfigRequiredVariable::default()
+                },
+            ),
+            (
+                String::from("port"),
+                YamlConfigRequiredVariable {
+                    default: Some(json!(8080)),
+                    ..YamlConfigRequiredVariable::default()
+                },
+            ),
+            (
+                String::from("token"),
+                YamlConfigRequiredVariable {
+                    secret: true,
+                    ..YamlConfigRequiredVariable::default()
+                },
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_ask_missing_variables() {
+        let mut io = IODebug::new();
+        io.add_stdin(String::from("j@k.com"));
+        io.add_stdin(String::new());
+        io.add_stdin(String::from("secret-token"));
+
+        let answers = ask_missing_variables(&io, &required(), &Variables::new()).unwrap();
+
+        let prompts = io.get_stdin_promps();
+        assert_eq!(prompts[0].msg, "email (Your e-mail): ");
+        assert_eq!(prompts[1].msg, "port [8080]: ");
+        assert!(prompts[2].secure);
+        let answers: Vec<(&str, &Value, bool)> = answers
+            .iter()
+            .map(|answer| (answer.name.as_str(), &answer.value, answer.can_be_saved))
+            .collect();
+        assert_eq!(
+            answers,
+            vec![
+                ("email", &json!("j@k.com"), true),
+                ("port", &json!(8080), false),
+                ("token", &json!("secret-token"), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ask_missing_variables_non_interactive() {
+        let mut io = IODebug::new();
+        io.set_interactive(false);
+        let variables = Variables::from_iter([(String::from("token"), json!("set"))]);
+
+        let result = ask_missing_variables(&io, &required(), &variables);
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Missing required variables: email (Your e-mail)"
+        );
+        assert!(io.get_stdin_promps().is_empty());
+    }
+
+    #[test]
+    fn test_missing_required_variables() {
+        let variables = Variables::from_iter([(String::from("token"), json!("set"))]);
+
+        let missing = missing_required_variables(&required(), &variables);
+
+        assert_eq!(missing, vec!["email (Your e-mail)"]);
+    }
+
+    #[test]
+    fn test_required_defaults() {
+        let defaults = required_defaults(&required(), &Variables::new());
+        assert_eq!(
+            defaults,
+            Variables::from_iter([(String::from("port"), json!(8080))])
+        );
+
+        let variables = Variables::from_iter([(String::from("port"), json!(80))]);
+        assert!(required_defaults(&required(), &variables).is_empty());
+    }
+}
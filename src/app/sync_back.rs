@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use super::inspect::{KEEPASS_HELPER, KeepassCall, find_keepass_calls};
+
+/// Value of the output that is different from the one of its entry
+#[derive(Debug, PartialEq)]
+pub struct SyncChange {
+    pub key: String,
+    pub call: KeepassCall,
+    pub value: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SyncChanges {
+    pub changes: Vec<SyncChange>,
+    /// Keys whose value has more than the value of the entry, like a URL
+    /// built with the password, they can't be written back
+    pub skipped: Vec<String>,
+}
+
+/// Keys of a dotenv template whose line has a single keepass call, the URIs
+/// of keepass_url can't be written back
+pub fn keepass_keys(template: &str) -> Vec<(String, KeepassCall)> {
+    let calls = find_keepass_calls(template);
+    template
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.trim_start();
+            if line.starts_with('#') {
+                return None;
+            }
+            let (key, _) = line
+                .strip_prefix("export ")
+                .unwrap_or(line)
+                .split_once('=')?;
+            let mut line_calls = calls.iter().filter(|call| call.line == index + 1);
+            match (line_calls.next(), line_calls.next()) {
+                (Some(call), None) if call.helper == KEEPASS_HELPER => {
+                    Some((key.trim().to_string(), call.clone()))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Compares the output on disk with the rendered one. Only the keys rendered
+/// with the same value of the entry are written back
+pub fn find_changes(
+    template: &str,
+    rendered: &BTreeMap<String, String>,
+    on_disk: &BTreeMap<String, String>,
+    entry_value: impl Fn(&KeepassCall) -> Option<String>,
+) -> SyncChanges {
+    let mut result = SyncChanges::default();
+    for (key, call) in keepass_keys(template) {
+        let (Some(rendered), Some(edited)) = (rendered.get(&key), on_disk.get(&key)) else {
+            continue;
+        };
+        if rendered == edited {
+            continue;
+        }
+        if entry_value(&call).as_ref() != Some(rendered) {
+            result.skipped.push(key);
+            continue;
+        }
+        result.changes.push(SyncChange {
+            key,
+            call,
+            value: edited.clone(),
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_changes() {
+        let template = "# {{keepass \"ignored\"}}\n\
+            HOST=localhost\n\
+            export PASSWORD={{keepass \"db\"}}\n\
+            USER=\"{{keepass \"db\" field=\"username\"}}\"\n\
+            URL=postgres://{{keepass \"db\"}}@localhost\n\
+            TOKEN={{keepass \"token\"}}";
+        let rendered = values(&[
+            ("HOST", "localhost"),
+            ("PASSWORD", "old"),
+            ("USER", "admin"),
+            ("URL", "postgres://old@localhost"),
+            ("TOKEN", "abc"),
+        ]);
+        let on_disk = values(&[
+            ("HOST", "remote"),
+            ("PASSWORD", "new"),
+            ("USER", "admin"),
+            ("URL", "postgres://new@localhost"),
+        ]);
+
+        let result = find_changes(template, &rendered, &on_disk, |call| {
+            match (call.path.join("/").as_str(), call.field.as_str()) {
+                ("db", "password") => Some(String::from("old")),
+                ("db", "username") => Some(String::from("admin")),
+                _ => None,
+            }
+        });
+
+        assert_eq!(result.skipped, vec!["URL"]);
+        assert_eq!(result.changes.len(), 1);
+        let change = &result.changes[0];
+        assert_eq!(change.key, "PASSWORD");
+        assert_eq!(change.call.path, vec!["db"]);
+        assert_eq!(change.call.line, 3);
+        assert_eq!(change.value, "new");
+    }
+}
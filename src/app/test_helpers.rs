@@ -6,9 +6,18 @@ pub mod tests {
     use super::super::commands::{Cli, Commands, ConfigCommands};
     use super::super::config::ConfigHandler;
     use super::super::execute;
+    use keepass::{Database, DatabaseKey};
     use std::cell::RefCell;
     use test_helpers::{TmpFile, normalize_separators};
 
+    pub fn get_test_db() -> Database {
+        let mut file =
+            std::fs::File::open("test_resources/test_db.kdbx").expect("Test DB cannot be open");
+
+        let key = DatabaseKey::new().with_password("MyTestPass");
+        Database::open(&mut file, key).expect("Cannot open the DB")
+    }
+
     pub struct TestConfig {
         config: TmpFile,
         project: TmpFile,
@@ -87,11 +96,12 @@ templates:
                     command: Commands::Config(ConfigCommands::AddFile {
                         name: Some(String::from("Test File 1")),
                         template: String::from("./test_resources/.env.example"),
-                        output: String::from("./test_resources/tmp/.env"),
+                        output: Some(String::from("./test_resources/tmp/.env")),
                         relative_to_input: false,
                         local: false,
+                        vars: vec![],
                     }),
-                    config: Some(String::from(test.get_file_path())),
+                    config: Some(test.get_file_path()),
                 },
                 &io,
             );
@@ -105,11 +115,12 @@ templates:
                     command: Commands::Config(ConfigCommands::AddFile {
                         name: Some(String::from("Test File 2")),
                         template: String::from("./test_resources/.env.example"),
-                        output: String::from("./test_resources/tmp/.env2"),
+                        output: Some(String::from("./test_resources/tmp/.env2")),
                         relative_to_input: false,
                         local: false,
+                        vars: vec![],
                     }),
-                    config: Some(String::from(test.get_file_path())),
+                    config: Some(test.get_file_path()),
                 },
                 &io,
             );
@@ -191,6 +202,7 @@ variables:
         stdins: RefCell<Vec<String>>,
         stdins_promp: RefCell<Vec<StdInPromp>>,
         stderrs: RefCell<Vec<String>>,
+        interactive: bool,
     }
 
     impl IODebug {
@@ -200,9 +212,15 @@ variables:
                 stdins_promp: RefCell::new(Vec::new()),
                 stderrs: RefCell::new(Vec::new()),
                 stdouts: RefCell::new(Vec::new()),
+                interactive: true,
             }
         }
 
+        pub fn set_interactive(&mut self, interactive: bool) -> &IODebug {
+            self.interactive = interactive;
+            self
+        }
+
         pub fn add_stdin(&mut self, input: String) -> &IODebug {
             self.stdins.borrow_mut().push(input);
             self
@@ -240,5 +258,9 @@ variables:
         fn error(&self, str: String) {
             self.stderrs.borrow_mut().push(str);
         }
+
+        fn is_interactive(&self) -> bool {
+            self.interactive
+        }
     }
 }
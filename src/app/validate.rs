@@ -0,0 +1,54 @@
+use serde_json::Value;
+
+use super::{
+    config::yaml::Variables,
+    inspect::{TemplateReferences, VariableReference},
+};
+
+/// Variables written in the output that have no value. The nested paths, like
+/// `app.name`, are checked by their first key
+pub fn missing_variables<'a>(
+    references: &'a TemplateReferences,
+    variables: &Variables,
+) -> Vec<&'a VariableReference> {
+    references
+        .variables
+        .iter()
+        .filter(|variable| variable.printed)
+        .filter(|variable| {
+            let key = variable
+                .name
+                .split(['.', '/'])
+                .next()
+                .unwrap_or(&variable.name);
+            variables.get(key).is_none_or(Value::is_null)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::app::inspect::inspect_template;
+
+    #[test]
+    fn test_missing_variables() {
+        let references = inspect_template(
+            "{{host}}:{{port}} {{app.name}} {{#if debug}}{{/if}} {{stringify token}} {{empty}}",
+        )
+        .unwrap();
+        let variables = Variables::from_iter([
+            (String::from("host"), json!("localhost")),
+            (String::from("empty"), Value::Null),
+        ]);
+
+        let missing: Vec<(&str, usize)> = missing_variables(&references, &variables)
+            .into_iter()
+            .map(|variable| (variable.name.as_str(), variable.column))
+            .collect();
+
+        assert_eq!(missing, vec![("port", 10), ("app.name", 19), ("empty", 73)]);
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    error::Error,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use super::{
+    BuildTarget, IOLogs, build_target,
+    config::yaml::Variables,
+    database::SharedDatabase,
+    errors_and_warnings::HelperErrors,
+    handlebars::{LibHandlebars, build_handlebars, record_reads},
+    lock::{OutputHashes, ReadInputs},
+    logs_prefix::LOG_PREFIX,
+    open_keepass_db,
+    partials::register_partials_dir,
+    read_keepass_db, template_files,
+};
+
+pub const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+fn modified(file: &str) -> Option<SystemTime> {
+    std::fs::metadata(Path::new(file))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Polls the modification time of the files, to keep it working the same on
+/// every platform and with editors that replace the file when saving
+pub struct FileWatcher {
+    files: HashMap<String, Option<SystemTime>>,
+}
+
+impl FileWatcher {
+    pub fn new(files: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            files: files
+                .into_iter()
+                .map(|file| {
+                    let time = modified(&file);
+                    (file, time)
+                })
+                .collect(),
+        }
+    }
+
+    /// Starts watching a file, the files already watched keep their time
+    pub fn watch(&mut self, file: String) {
+        if let Entry::Vacant(entry) = self.files.entry(file) {
+            let time = modified(entry.key());
+            entry.insert(time);
+        }
+    }
+
+    pub fn changed_files(&mut self) -> Vec<String> {
+        let mut changed = vec![];
+        for (file, last_modified) in self.files.iter_mut() {
+            let current = modified(file);
+            if current != *last_modified {
+                *last_modified = current;
+                changed.push(file.clone());
+            }
+        }
+        changed.sort();
+        changed
+    }
+}
+
+pub struct KeepassSource {
+    pub path: String,
+    pub password: String,
+    pub group: Option<String>,
+}
+
+pub struct WatchSession<'a> {
+    io: &'a dyn IOLogs,
+    keepass: KeepassSource,
+    handlebars: LibHandlebars<'a>,
+    errors_collector: &'a HelperErrors,
+    targets: Vec<BuildTarget>,
+    variables: Variables,
+    warnings_enabled: bool,
+    partials_dirs: Vec<String>,
+    /// Files used by each target, its template and the partials it uses
+    target_files: HashMap<String, Vec<String>>,
+    /// Record of the env and file helpers, registered again when the database
+    /// is reloaded
+    reads: Option<ReadInputs>,
+    /// Hashes of the last build of the outputs
+    output_hashes: OutputHashes,
+    watcher: FileWatcher,
+}
+
+/// The template and the partials of the target, only the template when it
+/// cannot be read
+fn target_files(target: &BuildTarget, partials_dirs: &[String]) -> Vec<String> {
+    match template_files(target, partials_dirs) {
+        Ok(files) => files.into_iter().map(|(file, _)| file).collect(),
+        Err(_) => vec![target.template.clone()],
+    }
+}
+
+impl<'a> WatchSession<'a> {
+    pub fn new(
+        io: &'a dyn IOLogs,
+        keepass: KeepassSource,
+        handlebars: LibHandlebars<'a>,
+        errors_collector: &'a HelperErrors,
+        targets: Vec<BuildTarget>,
+        variables: Variables,
+        warnings_enabled: bool,
+    ) -> Self {
+        let watcher = FileWatcher::new([keepass.path.clone()]);
+        let mut session = Self {
+            io,
+            keepass,
+            handlebars,
+            errors_collector,
+            targets,
+            variables,
+            warnings_enabled,
+            partials_dirs: Vec::new(),
+            target_files: HashMap::new(),
+            reads: None,
+            output_hashes: OutputHashes::default(),
+            watcher,
+        };
+        session.watch_targets();
+        session
+    }
+
+    /// Folders of the partials registered by name, their files are watched
+    /// for the targets using them
+    pub fn with_partials_dirs(mut self, partials_dirs: Vec<String>) -> Self {
+        self.partials_dirs = partials_dirs;
+        self.watch_targets();
+        self
+    }
+
+    /// Keeps recording what the env and file helpers read after the database
+    /// is reloaded
+    pub fn with_reads(mut self, reads: ReadInputs) -> Self {
+        self.reads = Some(reads);
+        self
+    }
+
+    /// Hashes of the outputs already built, to know if the encrypted ones
+    /// change
+    pub fn with_output_hashes(mut self, output_hashes: OutputHashes) -> Self {
+        self.output_hashes = output_hashes;
+        self
+    }
+
+    fn watch_targets(&mut self) {
+        for target in &self.targets {
+            let files = target_files(target, &self.partials_dirs);
+            for file in &files {
+                self.watcher.watch(file.clone());
+            }
+            self.target_files.insert(target.template.clone(), files);
+        }
+    }
+
+    /// Registers again the partials of the folders, the relative ones are read
+    /// on every render
+    fn reload_partials(&mut self) {
+        for dir in &self.partials_dirs {
+            if let Err(err) = register_partials_dir(&mut self.handlebars, dir) {
+                self.io.error(format!("{}: {err}", LOG_PREFIX.error));
+            }
+        }
+    }
+
+    fn reload_database(&mut self) -> Result<(), Box<dyn Error>> {
+        let db = match read_keepass_db(&self.keepass.path, &self.keepass.password) {
+            Ok(db) => db,
+            Err(_) => {
+                self.io.error(format!(
+                    "{}: The KeePass database cannot be opened with the previous password",
+                    LOG_PREFIX.warning
+                ));
+                let (db, password) = open_keepass_db(self.keepass.path.clone(), self.io)?;
+                self.keepass.password = password;
+                db
+            }
+        };
+        let templates = self.handlebars.get_templates().clone();
+        // The watch never writes the database, the entries are not generated
+        let db = SharedDatabase::new(db, false);
+        self.handlebars = build_handlebars(db, self.keepass.group.clone(), self.errors_collector);
+        if let Some(reads) = &self.reads {
+            record_reads(&mut self.handlebars, reads);
+        }
+        // Keeps the partials registered before starting the watch
+        for (name, template) in templates {
+            self.handlebars.register_template(&name, template);
+        }
+        Ok(())
+    }
+
+    /// Checks the watched files once and renders again the affected outputs,
+    /// returns the number of templates built
+    pub fn run_cycle(&mut self) -> usize {
+        let changed = self.watcher.changed_files();
+        if changed.is_empty() {
+            return 0;
+        }
+
+        let database_changed = changed.contains(&self.keepass.path);
+        if database_changed {
+            self.io.log(String::from(
+                "KeePass database changed, building all the templates",
+            ));
+            if let Err(err) = self.reload_database() {
+                self.io.error(format!(
+                    "{}: Cannot reload the KeePass database: {err}",
+                    LOG_PREFIX.error
+                ));
+                return 0;
+            }
+        }
+
+        let templates: Vec<&String> = self.target_files.keys().collect();
+        if changed
+            .iter()
+            .any(|file| *file != self.keepass.path && !templates.contains(&file))
+        {
+            self.reload_partials();
+        }
+
+        let mut errors_and_warnings = self.errors_collector.clone();
+        let mut built = 0;
+        for target in &self.targets {
+            let files = self
+                .target_files
+                .get(&target.template)
+                .cloned()
+                .unwrap_or_default();
+            if database_changed || files.iter().any(|file| changed.contains(file)) {
+                // The template can use other partials now
+                let files = target_files(target, &self.partials_dirs);
+                for file in &files {
+                    self.watcher.watch(file.clone());
+                }
+                self.target_files.insert(target.template.clone(), files);
+                build_target(
+                    &mut self.handlebars,
+                    self.io,
+                    target,
+                    &self.variables,
+                    &mut errors_and_warnings,
+                    self.warnings_enabled,
+                    &mut self.output_hashes,
+                );
+                built += 1;
+            }
+        }
+        built
+    }
+
+    pub fn run(mut self) {
+        self.io.log(String::from(
+            "Watching for changes in the templates and the KeePass database, press Ctrl+C to stop",
+        ));
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            self.run_cycle();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use serde_json::Value;
+    use test_helpers::TmpFile;
+
+    use super::*;
+    use crate::app::test_helpers::tests::{IODebug, get_test_db};
+
+    fn touch(file: &str) {
+        let later = SystemTime::now() + Duration::from_secs(5);
+        File::options()
+            .write(true)
+            .open(file)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_file_watcher() {
+        let file = TmpFile::new_uuid("test_resources/tmp", Some("hbs"));
+        file.write(String::from("A={{a}}"));
+        let mut watcher = FileWatcher::new([file.get(), String::from("missing-file")]);
+
+        assert!(watcher.changed_files().is_empty());
+        touch(&file.get());
+        assert_eq!(watcher.changed_files(), vec![file.get()]);
+        assert!(watcher.changed_files().is_empty());
+    }
+
+    #[test]
+    fn test_watch_cycle_renders_changed_templates() {
+        let template = TmpFile::new_uuid("test_resources/tmp", Some("hbs"));
+        template.write(String::from("VALUE={{keepass \"group1/test2\"}}"));
+        let other_template = TmpFile::new_uuid("test_resources/tmp", Some("hbs"));
+        other_template.write(String::from("OTHER={{other}}"));
+        let output = TmpFile::new_uuid("test_resources/tmp", Some("env"));
+        let other_output = TmpFile::new_uuid("test_resources/tmp", Some("env"));
+        let database = TmpFile::new_uuid("test_resources/tmp", Some("kdbx"));
+        std::fs::copy("test_resources/test_db.kdbx", database.get()).unwrap();
+
+        let mut io = IODebug::new();
+        io.add_stdin(String::from("MyTestPass"));
+        let (db, password) = open_keepass_db(database.get(), &io).unwrap();
+        let errors = HelperErrors::new();
+        let targets = vec![
+            BuildTarget {
+                name: template.get(),
+                label: template.get(),
+                template: template.get(),
+                output: output.get(),
+                ..BuildTarget::default()
+            },
+            BuildTarget {
+                name: other_template.get(),
+                label: other_template.get(),
+                template: other_template.get(),
+                output: other_output.get(),
+                ..BuildTarget::default()
+            },
+        ];
+        let mut session = WatchSession::new(
+            &io,
+            KeepassSource {
+                path: database.get(),
+                password,
+                group: None,
+            },
+            build_handlebars(db, None, &errors),
+            &errors,
+            targets,
+            Variables::from_iter([(String::from("other"), Value::from("value"))]),
+            true,
+        );
+
+        assert_eq!(session.run_cycle(), 0);
+
+        template.write(String::from("CHANGED={{keepass \"group1/test2\"}}"));
+        touch(&template.get());
+        assert_eq!(session.run_cycle(), 1);
+        assert_eq!(output.read(), "CHANGED=testing");
+        assert!(!Path::new(&other_output.get()).exists());
+
+        touch(&database.get());
+        assert_eq!(session.run_cycle(), 2);
+        assert_eq!(other_output.read(), "OTHER=value");
+        assert_eq!(io.get_stdin_promps().len(), 1);
+    }
+
+    #[test]
+    fn test_watch_cycle_renders_changed_partials() {
+        let dir = TmpFile::new_uuid("test_resources/tmp", None::<String>);
+        std::fs::create_dir_all(dir.get()).unwrap();
+        let partial = format!("{}/db.hbs", dir.get());
+        std::fs::write(&partial, "DB={{keepass \"group1/test2\"}}").unwrap();
+        let template = TmpFile::new_uuid("test_resources/tmp", Some("hbs"));
+        template.write(String::from("{{> db}}"));
+        let output = TmpFile::new_uuid("test_resources/tmp", Some("env"));
+
+        let io = IODebug::new();
+        let errors = HelperErrors::new();
+        let mut handlebars = build_handlebars(get_test_db(), None, &errors);
+        register_partials_dir(&mut handlebars, &dir.get()).unwrap();
+        let mut session = WatchSession::new(
+            &io,
+            KeepassSource {
+                path: String::from("test_resources/test_db.kdbx"),
+                password: String::from("MyTestPass"),
+                group: None,
+            },
+            handlebars,
+            &errors,
+            vec![BuildTarget {
+                template: template.get(),
+                output: output.get(),
+                ..BuildTarget::default()
+            }],
+            Variables::new(),
+            true,
+        )
+        .with_partials_dirs(vec![dir.get()]);
+
+        std::fs::write(&partial, "PASSWORD={{keepass \"group1/test2\"}}").unwrap();
+        touch(&partial);
+        assert_eq!(session.run_cycle(), 1);
+        assert_eq!(output.read(), "PASSWORD=testing");
+
+        std::fs::remove_dir_all(dir.get()).unwrap();
+    }
+}
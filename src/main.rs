@@ -1,7 +1,7 @@
 use clap::{CommandFactory, Parser};
 use clap_complete::generate;
 use std::error::Error;
-use std::io;
+use std::io::{self, IsTerminal};
 
 mod app;
 use app::IOLogs;
@@ -29,6 +29,10 @@ impl IOLogs for Console {
             Ok(input.trim().to_string())
         }
     }
+
+    fn is_interactive(&self) -> bool {
+        io::stdin().is_terminal()
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -3,7 +3,7 @@ use std::process::Command;
 #[test]
 fn test_completion_command() {
     let output = Command::new("cargo")
-        .args(&["run", "--", "completion", "bash"])
+        .args(["run", "--", "completion", "bash"])
         .output()
         .expect("Failed to execute command");
 
@@ -19,7 +19,7 @@ templates: null"
 fn test_load_old_config() {
     let config = create_v1_config();
     let output = Command::new("cargo")
-        .args(&[
+        .args([
             "run",
             "--",
             "--config",
@@ -40,7 +40,7 @@ fn test_load_old_config() {
 fn test_upgrade_old_config() {
     let config = create_v1_config();
     Command::new("cargo")
-        .args(&[
+        .args([
             "run",
             "--",
             "--config",